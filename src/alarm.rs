@@ -0,0 +1,154 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration as ChronoDuration, Local};
+
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::time;
+use rocket::tokio::time::Duration;
+
+use serde_with::{serde_as, DurationMilliSeconds};
+
+use yansi::Paint;
+
+use crate::procedural::Wake;
+use crate::schedule::TimeOfDay;
+use crate::storage::{Collection, StorageError};
+use crate::{Color, Pattern, SharedZones, Zones};
+
+// longest ramp an alarm can have, which also keeps it within the range of
+// chrono's durations
+const MAX_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A pending wake-up that ramps a zone up to full brightness at `at`
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Alarm {
+    pub at: DateTime<Local>,
+    #[serde_as(as = "DurationMilliSeconds")]
+    pub duration: Duration,
+}
+
+impl Alarm {
+    /// The next alarm at `time`, or `None` if the ramp would be empty or longer
+    /// than a day
+    pub fn new(time: TimeOfDay, duration: Duration) -> Option<Alarm> {
+        if duration.is_zero() || duration > MAX_DURATION {
+            return None;
+        }
+
+        Some(Alarm {
+            at: time.next_after(Local::now())?,
+            duration,
+        })
+    }
+
+    /// How far into the ramp the alarm is, or `None` before it starts
+    fn elapsed(&self, now: DateTime<Local>) -> Option<Duration> {
+        let duration = ChronoDuration::from_std(self.duration).ok()?;
+
+        (now - (self.at - duration)).to_std().ok()
+    }
+
+    fn pattern(&self) -> Pattern {
+        Pattern::Wake(Wake {
+            duration: self.duration,
+        })
+    }
+
+    fn final_color(&self) -> Color {
+        Wake {
+            duration: self.duration,
+        }
+        .color(self.duration)
+    }
+}
+
+/// Alarms keyed by the zone they wake up
+pub type Alarms = Collection<Alarm>;
+
+pub type SharedAlarms = Arc<Alarms>;
+
+/// Cancel a zone's alarm, turning the lights back off if it was already
+/// ramping up, and returning whether there was an alarm to cancel
+pub async fn cancel(zones: &Zones, alarms: &Alarms, zone: &str) -> Result<bool, StorageError> {
+    let alarm = match alarms.remove(zone).await? {
+        Some(alarm) => alarm,
+        None => return Ok(false),
+    };
+
+    if alarm.elapsed(Local::now()).is_some() {
+        if let Some(lights) = zones.get(zone) {
+            let mut lights = lights.lock().await;
+
+            if matches!(lights.get_pattern(), Pattern::Wake(_)) {
+                lights.set_pattern(&Pattern::Off, None);
+            }
+        }
+    }
+
+    Ok(true)
+}
+
+/// Start the wake-up ramp of pending alarms when it is time, including
+/// partway through if the daemon was restarted during the ramp
+pub async fn alarm_runner(zones: SharedZones, alarms: SharedAlarms, period: Duration) {
+    println!(
+        "{}{}",
+        Paint::masked("⏰ "),
+        Paint::default("Alarm runner started").bold()
+    );
+
+    let mut started = BTreeSet::new();
+
+    let mut interval = time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        let now = Local::now();
+        let pending = alarms.all().await;
+
+        started.retain(|(zone, at)| pending.get(zone).map(|alarm| alarm.at) == Some(*at));
+
+        for (zone, alarm) in pending {
+            let lights = match zones.get(&zone) {
+                Some(lights) => lights,
+                None => continue,
+            };
+
+            if now >= alarm.at {
+                // hold the end of the ramp as a solid color so that restoring
+                // the saved state later does not start the ramp over
+                {
+                    let mut lights = lights.lock().await;
+
+                    if matches!(lights.get_pattern(), Pattern::Wake(_)) {
+                        lights.set_pattern(&Pattern::Solid(alarm.final_color()), None);
+                    }
+                }
+
+                // alarms missed entirely while the daemon was down are dropped
+                if let Err(err) = alarms.remove(&zone).await {
+                    eprintln!("Failed to remove finished alarm for zone {}: {}", zone, err);
+                }
+
+                continue;
+            }
+
+            if let Some(elapsed) = alarm.elapsed(now) {
+                if started.insert((zone.clone(), alarm.at)) {
+                    let mut lights = lights.lock().await;
+
+                    // the ramp starts from off, so the lights can be brought
+                    // to full brightness without a visible jump
+                    lights.set_pattern_since(&alarm.pattern(), elapsed);
+                    lights.set_brightness(u8::MAX);
+
+                    println!("Wake-up alarm started for zone {}", zone);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use rocket::serde::Deserialize;
+
+use crate::output::{CalibrationConfig, OutputConfig};
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ZoneConfig {
+    #[serde(default)]
+    pub output: OutputConfig,
+    pub calibration: Option<CalibrationConfig>,
+}
+
+/// Where the lights are, for sun-relative schedules
+#[derive(Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Location {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(-90.0..=90.0).contains(&self.latitude) {
+            return Err(format!(
+                "latitude {} must be between -90 and 90 degrees",
+                self.latitude
+            ));
+        }
+
+        if !(-180.0..=180.0).contains(&self.longitude) {
+            return Err(format!(
+                "longitude {} must be between -180 and 180 degrees",
+                self.longitude
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// What the lights show when the daemon starts
+#[derive(Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum PowerOn {
+    #[default]
+    Restore,
+    Off,
+    Preset(String),
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct LightsConfig {
+    pub output: Option<OutputConfig>,
+    pub calibration: Option<CalibrationConfig>,
+
+    #[serde(default)]
+    pub zones: BTreeMap<String, ZoneConfig>,
+    #[serde(default = "default_zone")]
+    pub default_zone: String,
+
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+    #[serde(default)]
+    pub power_on: PowerOn,
+    pub location: Option<Location>,
+}
+
+fn default_zone() -> String {
+    String::from("default")
+}
+
+fn default_data_dir() -> PathBuf {
+    PathBuf::from("data")
+}
+
+/// Whether a zone or preset name is safe to use in URLs and OSC addresses
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl LightsConfig {
+    /// Configured zones, falling back to a single default zone using the
+    /// top-level output when no zones are configured
+    pub fn zones(&self) -> Result<BTreeMap<String, ZoneConfig>, String> {
+        if self.zones.is_empty() {
+            let mut zones = BTreeMap::new();
+
+            zones.insert(
+                self.default_zone.clone(),
+                ZoneConfig {
+                    output: self.output.clone().unwrap_or_default(),
+                    calibration: self.calibration.clone(),
+                },
+            );
+
+            return Ok(zones);
+        }
+
+        // reject top-level output and calibration alongside zones rather than
+        // silently dropping them, e.g. when a zone is added to an existing
+        // single-strip config
+        for (key, set) in [
+            ("output", self.output.is_some()),
+            ("calibration", self.calibration.is_some()),
+        ] {
+            if set {
+                return Err(format!(
+                    "top-level {} is not used when zones are configured, move it into a zone",
+                    key
+                ));
+            }
+        }
+
+        for name in self.zones.keys() {
+            if !valid_name(name) {
+                return Err(format!(
+                    "zone name \"{}\" may only contain letters, digits, '-' and '_'",
+                    name
+                ));
+            }
+        }
+
+        if !self.zones.contains_key(&self.default_zone) {
+            return Err(format!(
+                "default zone \"{}\" is not one of the configured zones",
+                self.default_zone
+            ));
+        }
+
+        Ok(self.zones.clone())
+    }
+}
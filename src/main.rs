@@ -1,34 +1,50 @@
 #[macro_use]
 extern crate rocket;
 
+mod alarm;
+mod config;
+mod output;
+mod procedural;
+mod schedule;
+mod state;
+mod storage;
+mod sun;
+
 use std::env;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fmt::{Display, Formatter, Result as FmtResult};
-use std::net::SocketAddr;
-use std::num::ParseIntError;
+use std::io::Result as IoResult;
+use std::num::{NonZeroU32, ParseIntError};
 use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process;
 use std::str::FromStr;
 use std::sync::Arc;
 
+use rocket::config::pretty_print_error;
+use rocket::data::{IoHandler, IoStream};
 use rocket::fairing::AdHoc;
 use rocket::form::{Error as FormError, Form, FromFormField, Result as FormResult, ValueField};
 use rocket::fs::NamedFile;
 use rocket::http::Status;
-use rocket::response::Redirect;
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::response::{self, Redirect, Responder, Response};
 use rocket::{Config, State};
 
 use rocket::futures::sink::SinkExt;
 use rocket::futures::stream::{SplitSink, StreamExt};
 
 use rocket::serde::json::serde_json;
-use rocket::serde::json::Json;
+use rocket::serde::json::{Json, Value};
 use rocket::serde::{Deserialize, Serialize};
 
 use rocket::tokio;
+use rocket::tokio::io::{AsyncRead, AsyncWrite};
 use rocket::tokio::net::{TcpListener, TcpStream, UdpSocket};
-use rocket::tokio::sync::Mutex;
+use rocket::tokio::sync::mpsc::{self, error::TrySendError};
+use rocket::tokio::sync::{watch, Mutex, Notify};
 use rocket::tokio::time;
 use rocket::tokio::time::{Duration, Instant};
 
@@ -36,16 +52,31 @@ use rocket_dyn_templates::Template;
 
 use rosc::{OscPacket, OscType};
 
-use rppal::gpio::{Gpio, OutputPin};
-
 use serde_with::{serde_as, DurationMilliSeconds};
 
 use tokio_tungstenite::tungstenite::error::ProtocolError as WSProtocolError;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::handshake::server::{
+    ErrorResponse as WSErrorResponse, Request as WSRequest, Response as WSResponse,
+};
+use tokio_tungstenite::tungstenite::http::StatusCode as WSStatusCode;
+use tokio_tungstenite::tungstenite::protocol::Role as WSRole;
 use tokio_tungstenite::tungstenite::{Error as WSError, Message as WSMessage};
 use tokio_tungstenite::WebSocketStream;
 
 use yansi::Paint;
 
+use alarm::{alarm_runner, Alarm, Alarms, SharedAlarms};
+use config::{valid_name, LightsConfig, Location, PowerOn, ZoneConfig};
+use output::{
+    CalibratedOutput, GpioOutput, Levels, Output, OutputConfig, SimulatedLog, SimulatedOutput,
+    SimulatedWrite, Ws2812Output,
+};
+use procedural::{default_wake_duration, Breathe, Candle, Rainbow, Strobe, Wake};
+use schedule::{scheduler, Schedule, Schedules, SharedSchedules, TimeOfDay};
+use state::{state_saver, SavedState};
+use storage::Collection;
+
 #[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 struct Color {
@@ -132,6 +163,80 @@ impl Display for Color {
     }
 }
 
+impl Color {
+    fn mix(self, other: Color, amount: f64) -> Color {
+        let amount = amount.clamp(0.0, 1.0);
+        let blend =
+            |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * amount).round() as u8;
+
+        Color {
+            red: blend(self.red, other.red),
+            green: blend(self.green, other.green),
+            blue: blend(self.blue, other.blue),
+            white: blend(self.white, other.white),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Brightness {
+    brightness: u8,
+}
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "kebab-case")]
+enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    Step,
+}
+
+impl Easing {
+    fn apply(self, progress: f64) -> f64 {
+        let t = progress.clamp(0.0, 1.0);
+
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+            // hold the previous color until the transition is over
+            Easing::Step => {
+                if t < 1.0 {
+                    0.0
+                } else {
+                    1.0
+                }
+            }
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Transition {
+    #[serde_as(as = "DurationMilliSeconds")]
+    duration: Duration,
+    #[serde(default)]
+    easing: Easing,
+}
+
+impl Transition {
+    fn progress(&self, elapsed: Duration) -> f64 {
+        if self.duration.is_zero() {
+            1.0
+        } else {
+            self.easing
+                .apply(elapsed.as_secs_f64() / self.duration.as_secs_f64())
+        }
+    }
+}
+
 #[serde_as]
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
@@ -139,6 +244,168 @@ struct Frame {
     color: Color,
     #[serde_as(as = "DurationMilliSeconds")]
     duration: Duration,
+    // fades in from the previous frame's color at the start of this frame
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transition: Option<Transition>,
+}
+
+// crossfade from the pixels displayed when the pattern was changed
+struct Fade {
+    from: Vec<Color>,
+    duration: Duration,
+    instant: Instant,
+}
+
+impl Fade {
+    fn finished(&self) -> bool {
+        self.instant.elapsed() >= self.duration
+    }
+
+    fn apply(&self, pixel: usize, color: Color) -> Option<Color> {
+        let elapsed = self.instant.elapsed();
+
+        if elapsed >= self.duration {
+            None
+        } else {
+            Some(self.from[pixel].mix(color, elapsed.as_secs_f64() / self.duration.as_secs_f64()))
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "kebab-case")]
+enum Playback {
+    #[default]
+    Loop,
+    Once,
+    // a count of zero is rejected rather than playing once
+    Repeat(NonZeroU32),
+    PingPong,
+}
+
+impl Playback {
+    fn finished(self, cycles: u32) -> bool {
+        match self {
+            Playback::Loop | Playback::PingPong => false,
+            Playback::Once => cycles >= 1,
+            Playback::Repeat(count) => cycles >= count.get(),
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", untagged)]
+enum CustomContent {
+    Frames(Vec<Frame>),
+    Playback {
+        frames: Vec<Frame>,
+        #[serde(default)]
+        mode: Playback,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        then: Option<Box<Pattern>>,
+    },
+}
+
+// accepts either a bare list of looping frames or an object with a playback
+// mode, and serializes back to a bare list when the defaults are used
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(
+    crate = "rocket::serde",
+    from = "CustomContent",
+    into = "CustomContent"
+)]
+struct CustomPattern {
+    frames: Vec<Frame>,
+    mode: Playback,
+    then: Option<Box<Pattern>>,
+}
+
+impl From<CustomContent> for CustomPattern {
+    fn from(content: CustomContent) -> Self {
+        match content {
+            CustomContent::Frames(frames) => CustomPattern {
+                frames,
+                mode: Playback::Loop,
+                then: None,
+            },
+            CustomContent::Playback { frames, mode, then } => CustomPattern { frames, mode, then },
+        }
+    }
+}
+
+impl From<CustomPattern> for CustomContent {
+    fn from(custom: CustomPattern) -> Self {
+        if custom.mode == Playback::Loop && custom.then.is_none() {
+            CustomContent::Frames(custom.frames)
+        } else {
+            CustomContent::Playback {
+                frames: custom.frames,
+                mode: custom.mode,
+                then: custom.then,
+            }
+        }
+    }
+}
+
+impl CustomPattern {
+    // number of steps in one cycle, where ping-pong plays the frames forwards
+    // and then backwards without repeating the first and last frame
+    fn steps(&self) -> usize {
+        match self.mode {
+            Playback::PingPong if self.frames.len() > 1 => self.frames.len() * 2 - 2,
+            _ => self.frames.len(),
+        }
+    }
+
+    fn index(&self, step: usize) -> usize {
+        if step < self.frames.len() {
+            step
+        } else {
+            self.frames.len() * 2 - 2 - step
+        }
+    }
+
+    // moves `step` past every frame that had ended `elapsed` after it
+    // started, returning how far into the frame it stops at playback is
+    fn advance(&self, step: &mut usize, cycles: &mut u32, mut elapsed: Duration) -> Duration {
+        let steps = self.steps();
+
+        if *step >= steps {
+            *step = 0;
+        }
+
+        // finished patterns hold their last frame
+        while !self.mode.finished(*cycles) && elapsed >= self.frames[self.index(*step)].duration {
+            elapsed -= self.frames[self.index(*step)].duration;
+
+            if *step + 1 < steps {
+                *step += 1;
+            } else {
+                *cycles = cycles.saturating_add(1);
+
+                if !self.mode.finished(*cycles) {
+                    *step = 0;
+                }
+            }
+        }
+
+        elapsed
+    }
+
+    fn color(&self, step: usize, elapsed: Duration) -> Color {
+        let frame = &self.frames[self.index(step)];
+
+        match &frame.transition {
+            Some(transition) => {
+                let previous = &self.frames[self.index((step + self.steps() - 1) % self.steps())];
+
+                previous
+                    .color
+                    .mix(frame.color, transition.progress(elapsed))
+            }
+            None => frame.color,
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -151,73 +418,137 @@ struct Frame {
 enum Pattern {
     Off,
     Solid(Color),
-    Custom(Vec<Frame>),
+    Custom(CustomPattern),
+    Rainbow(Rainbow),
+    Breathe(Breathe),
+    Strobe(Strobe),
+    Candle(Candle),
+    Fire,
+    Wake(Wake),
 }
 
-struct Output {
-    frequency: f64,
+// output retries back off exponentially between these bounds
+const RETRY_MIN: Duration = Duration::from_millis(100);
+const RETRY_MAX: Duration = Duration::from_secs(30);
 
-    red: OutputPin,
-    green: OutputPin,
-    blue: OutputPin,
-    white: OutputPin,
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+enum HealthStatus {
+    Ok,
+    Failing,
 }
 
-impl Output {
-    fn set(&mut self, color: Color) -> rppal::gpio::Result<()> {
-        self.red
-            .set_pwm_frequency(self.frequency, color.red as f64 / 255.0)?;
-        self.green
-            .set_pwm_frequency(self.frequency, color.green as f64 / 255.0)?;
-        self.blue
-            .set_pwm_frequency(self.frequency, color.blue as f64 / 255.0)?;
-        self.white
-            .set_pwm_frequency(self.frequency, color.white as f64 / 255.0)?;
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct Health {
+    status: HealthStatus,
+    error: Option<String>,
+    failures: u32,
+    #[serde_as(as = "Option<DurationMilliSeconds>")]
+    retry_in: Option<Duration>,
+}
 
-        Ok(())
-    }
+struct SleepTimer {
+    ends: Instant,
+    fade: Duration,
+}
+
+#[serde_as]
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct TimerStatus {
+    #[serde_as(as = "DurationMilliSeconds")]
+    remaining: Duration,
+    #[serde_as(as = "DurationMilliSeconds")]
+    fade: Duration,
+}
+
+/// Snapshot of a zone published to subscribers whenever it changes
+#[derive(Clone)]
+struct LightState {
+    color: Color,
+    pattern: Arc<Pattern>,
+    // the pattern and brightness have changed when the revision has
+    revision: u64,
+    brightness: u8,
+    // remaining time in whole seconds, so the timer counts down once per
+    // second rather than changing on every tick
+    timer: Option<TimerStatus>,
 }
 
 struct Lights {
-    output: Output,
+    zone: String,
+    output: Box<dyn Output>,
     pattern: Pattern,
+    brightness: u8,
+    fade: Option<Fade>,
+    // bumped on every change that should be persisted
+    revision: u64,
 
     frame: usize,
+    cycles: u32,
     instant: Instant,
+    timer: Option<SleepTimer>,
+    state: watch::Sender<LightState>,
+    // wakes the render loop early when a command changes the lights
+    changed: Arc<Notify>,
+
+    last: Vec<Levels>,
+    dirty: bool,
 
-    last: Color,
+    error: Option<String>,
+    failures: u32,
+    retry_at: Option<Instant>,
 }
 
 impl Lights {
-    fn new(output: Output, pattern: Pattern) -> Lights {
-        let mut lights = Lights {
-            output,
-            pattern,
+    fn new(zone: String, output: Box<dyn Output>, pattern: Pattern, brightness: u8) -> Lights {
+        let pixels = output.pixels();
 
-            frame: 0,
-            instant: Instant::now(),
-            last: Color {
+        let (state, _) = watch::channel(LightState {
+            color: Color {
                 red: 0,
                 green: 0,
                 blue: 0,
                 white: 0,
             },
+            pattern: Arc::new(pattern.clone()),
+            revision: 0,
+            brightness,
+            timer: None,
+        });
+
+        let mut lights = Lights {
+            zone,
+            output,
+            pattern,
+            brightness,
+            fade: None,
+            revision: 0,
+
+            frame: 0,
+            cycles: 0,
+            instant: Instant::now(),
+            timer: None,
+            state,
+            changed: Arc::new(Notify::new()),
+            last: vec![Levels::default(); pixels],
+            dirty: true,
+
+            error: None,
+            failures: 0,
+            retry_at: None,
         };
 
-        lights
-            .output
-            .set(Color {
-                red: 0,
-                green: 0,
-                blue: 0,
-                white: 0,
-            })
-            .expect("Lights output failure");
+        lights.flush();
 
         lights
     }
 
-    fn get(&self) -> Color {
+    // color of the pattern at `position` from 0 to 1 along the strip, before
+    // any crossfade
+    fn pattern_color(&self, position: f64) -> Color {
         match &self.pattern {
             Pattern::Off => Color {
                 red: 0,
@@ -226,8 +557,8 @@ impl Lights {
                 white: 0,
             },
             Pattern::Solid(color) => *color,
-            Pattern::Custom(frames) => {
-                if frames.is_empty() {
+            Pattern::Custom(custom) => {
+                if custom.frames.is_empty() {
                     Color {
                         red: 0,
                         green: 0,
@@ -235,66 +566,344 @@ impl Lights {
                         white: 0,
                     }
                 } else {
-                    frames[self.frame].color
+                    custom.color(self.frame, self.instant.elapsed())
+                }
+            }
+            Pattern::Rainbow(rainbow) => rainbow.color(self.instant.elapsed(), position),
+            Pattern::Breathe(breathe) => breathe.color(self.instant.elapsed()),
+            Pattern::Strobe(strobe) => strobe.color(self.instant.elapsed()),
+            Pattern::Candle(candle) => candle.color(self.instant.elapsed()),
+            Pattern::Fire => procedural::fire(self.instant.elapsed()),
+            Pattern::Wake(wake) => wake.color(self.instant.elapsed()),
+        }
+    }
+
+    // whether the pattern varies along the strip rather than filling it
+    fn spatial(&self) -> bool {
+        matches!(&self.pattern, Pattern::Rainbow(rainbow) if rainbow.spread != 0.0)
+    }
+
+    /// Color of the first pixel, which is what the API reports
+    fn get(&self) -> Color {
+        let color = self.pattern_color(0.0);
+
+        self.fade
+            .as_ref()
+            .and_then(|fade| fade.apply(0, color))
+            .unwrap_or(color)
+    }
+
+    /// Render one color per pixel of the output
+    fn render(&self) -> Vec<Color> {
+        let pixels = self.last.len();
+
+        let mut buffer = if self.spatial() {
+            (0..pixels)
+                .map(|pixel| self.pattern_color(pixel as f64 / pixels as f64))
+                .collect()
+        } else {
+            // single color patterns fill the whole strip
+            vec![self.pattern_color(0.0); pixels]
+        };
+
+        if let Some(fade) = &self.fade {
+            for (pixel, color) in buffer.iter_mut().enumerate() {
+                if let Some(faded) = fade.apply(pixel, *color) {
+                    *color = faded;
                 }
             }
         }
+
+        buffer
     }
 
-    fn set(&mut self, color: Color) {
-        self.pattern = Pattern::Solid(color);
+    fn set(&mut self, color: Color, transition: Option<Duration>) {
+        self.set_pattern(&Pattern::Solid(color), transition);
     }
 
     fn get_pattern(&self) -> &Pattern {
         &self.pattern
     }
 
-    fn set_pattern(&mut self, pattern: &Pattern) {
+    // explicitly setting a new pattern cancels the sleep timer
+    fn set_pattern(&mut self, pattern: &Pattern, transition: Option<Duration>) {
+        self.timer = None;
+        self.replace_pattern(pattern, transition);
+    }
+
+    fn replace_pattern(&mut self, pattern: &Pattern, transition: Option<Duration>) {
+        self.fade = match transition {
+            Some(duration) if !duration.is_zero() => Some(Fade {
+                from: self.render(),
+                duration,
+                instant: Instant::now(),
+            }),
+            _ => None,
+        };
+
         self.pattern = pattern.clone();
+        self.frame = 0;
+        self.cycles = 0;
+        self.instant = Instant::now();
+        self.revision += 1;
+        self.changed.notify_one();
     }
 
-    fn tick(&mut self) {
-        let next = match &self.pattern {
-            Pattern::Off => Color {
-                red: 0,
-                green: 0,
-                blue: 0,
-                white: 0,
+    // starts partway through the pattern, e.g. to resume it after a restart
+    fn set_pattern_since(&mut self, pattern: &Pattern, elapsed: Duration) {
+        self.set_pattern(pattern, None);
+
+        if let Some(instant) = Instant::now().checked_sub(elapsed) {
+            self.instant = instant;
+        }
+    }
+
+    fn timer(&self) -> Option<TimerStatus> {
+        self.timer.as_ref().map(|timer| TimerStatus {
+            remaining: timer.ends.saturating_duration_since(Instant::now()),
+            fade: timer.fade,
+        })
+    }
+
+    /// Keep the current pattern running for `duration`, then fade to off
+    fn set_timer(&mut self, duration: Duration, fade: Duration) {
+        self.timer = Some(SleepTimer {
+            ends: Instant::now() + duration,
+            fade,
+        });
+        self.changed.notify_one();
+    }
+
+    fn cancel_timer(&mut self) -> bool {
+        self.changed.notify_one();
+        self.timer.take().is_some()
+    }
+
+    fn get_brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+        self.revision += 1;
+        self.changed.notify_one();
+    }
+
+    fn subscribe(&self) -> watch::Receiver<LightState> {
+        self.state.subscribe()
+    }
+
+    fn changed(&self) -> Arc<Notify> {
+        Arc::clone(&self.changed)
+    }
+
+    fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    fn health(&self) -> Health {
+        Health {
+            status: if self.error.is_some() {
+                HealthStatus::Failing
+            } else {
+                HealthStatus::Ok
             },
-            Pattern::Solid(color) => *color,
-            Pattern::Custom(frames) => {
-                if frames.is_empty() {
-                    self.instant = Instant::now();
-                    self.frame = 0;
+            error: self.error.clone(),
+            failures: self.failures,
+            retry_in: self
+                .retry_at
+                .map(|retry_at| retry_at.saturating_duration_since(Instant::now())),
+        }
+    }
 
-                    Color {
-                        red: 0,
-                        green: 0,
-                        blue: 0,
-                        white: 0,
-                    }
+    // writes the buffer if it has not reached the output yet, backing off
+    // after failures so a broken output does not stall the render loop
+    fn flush(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        if let Some(retry_at) = self.retry_at {
+            if Instant::now() < retry_at {
+                return;
+            }
+        }
+
+        match self.output.set(&self.last) {
+            Ok(()) => {
+                if self.failures > 0 {
+                    println!(
+                        "{}{} {} {}",
+                        Paint::masked("💡 "),
+                        Paint::default("Output recovered for zone").bold(),
+                        Paint::default(&self.zone).bold().underline(),
+                        Paint::default(format!("after {} failures", self.failures)).bold()
+                    );
+                }
+
+                self.dirty = false;
+                self.error = None;
+                self.failures = 0;
+                self.retry_at = None;
+            }
+            Err(err) => {
+                self.failures = self.failures.saturating_add(1);
+
+                let backoff = RETRY_MIN
+                    .saturating_mul(1 << (self.failures - 1).min(16))
+                    .min(RETRY_MAX);
+
+                eprintln!(
+                    "{} Output failure for zone {} (attempt {}), retrying in {}ms: {}",
+                    Paint::red("Error:").bold(),
+                    self.zone,
+                    self.failures,
+                    backoff.as_millis(),
+                    err
+                );
+
+                self.error = Some(err.to_string());
+                self.retry_at = Some(Instant::now() + backoff);
+            }
+        }
+    }
+
+    fn publish(&self, color: Color) {
+        let timer = self.timer().map(|timer| TimerStatus {
+            remaining: Duration::from_secs(timer.remaining.as_secs()),
+            fade: timer.fade,
+        });
+
+        self.state.send_if_modified(|state| {
+            if state.color == color && state.revision == self.revision && state.timer == timer {
+                return false;
+            }
+
+            // only clone the pattern when it may have changed
+            if state.revision != self.revision {
+                state.pattern = Arc::new(self.pattern.clone());
+                state.revision = self.revision;
+                state.brightness = self.brightness;
+            }
+
+            state.color = color;
+            state.timer = timer;
+
+            true
+        });
+    }
+
+    /// Render the current color, returning when the output next needs to
+    /// change, or `None` if it only changes on the next command
+    fn tick(&mut self, chronon: Duration) -> Option<Instant> {
+        if let Some(timer) = &self.timer {
+            if Instant::now() >= timer.ends {
+                let fade = timer.fade;
+
+                self.timer = None;
+                self.replace_pattern(&Pattern::Off, Some(fade));
+            }
+        }
+
+        let mut then = None;
+
+        if let Pattern::Custom(custom) = &self.pattern {
+            if custom.frames.is_empty() {
+                self.instant = Instant::now();
+                self.frame = 0;
+            } else {
+                let elapsed = self.instant.elapsed();
+                let remaining = custom.advance(&mut self.frame, &mut self.cycles, elapsed);
+
+                // frame boundaries stay relative to the start of the pattern
+                self.instant += elapsed - remaining;
+
+                if custom.mode.finished(self.cycles) {
+                    then = custom.then.clone();
+                }
+            }
+        }
+
+        let color = self.get();
+        let pixels = self.render();
+
+        if self.fade.as_ref().is_some_and(Fade::finished) {
+            self.fade = None;
+        }
+
+        // the follow-up pattern takes over from the next tick
+        if let Some(pattern) = then {
+            self.replace_pattern(&pattern, None);
+        }
+
+        self.publish(color);
+
+        // brightness is applied in floating point so that it does not round
+        // away dim colors before calibration
+        let pixels: Vec<Levels> = pixels
+            .into_iter()
+            .map(|pixel| Levels::new(pixel, self.brightness))
+            .collect();
+
+        if self.last != pixels {
+            self.last = pixels;
+            self.dirty = true;
+        }
+
+        self.flush();
+
+        self.deadline(chronon)
+    }
+
+    fn deadline(&self, chronon: Duration) -> Option<Instant> {
+        let now = Instant::now();
+        let mut deadlines = Vec::new();
+
+        let animating = match &self.pattern {
+            Pattern::Off | Pattern::Solid(_) => false,
+            Pattern::Custom(custom) => {
+                if custom.frames.is_empty() || custom.mode.finished(self.cycles) {
+                    false
                 } else {
-                    if self.frame >= frames.len() {
-                        self.frame = 0;
-                    }
+                    let frame = &custom.frames[custom.index(self.frame)];
 
-                    while self.instant.elapsed() >= frames[self.frame].duration {
-                        self.instant = self
-                            .instant
-                            .checked_add(frames[self.frame].duration)
-                            .unwrap();
-                        self.frame = (self.frame + 1) % frames.len();
-                    }
+                    // frame boundaries are kept relative to the start of the
+                    // frame, so waking up late does not drift the pattern
+                    deadlines.push(self.instant + frame.duration);
 
-                    frames[self.frame].color
+                    frame
+                        .transition
+                        .as_ref()
+                        .is_some_and(|transition| self.instant.elapsed() < transition.duration)
                 }
             }
+            // procedural patterns stop rendering once they settle on a color,
+            // e.g. a finished wake-up ramp or a strobe without a rate
+            Pattern::Rainbow(rainbow) => rainbow.animating(),
+            Pattern::Breathe(breathe) => breathe.animating(),
+            Pattern::Strobe(strobe) => strobe.animating(),
+            Pattern::Candle(candle) => candle.animating(),
+            Pattern::Fire => true,
+            Pattern::Wake(wake) => wake.animating(self.instant.elapsed()),
         };
 
-        if next != self.last {
-            self.output.set(next).expect("Lights output failure");
-            self.last = next;
+        if animating || self.fade.is_some() {
+            deadlines.push(now + chronon);
+        }
+
+        if let Some(timer) = &self.timer {
+            let remaining = timer.ends.saturating_duration_since(now);
+
+            // the published remaining time counts down in whole seconds
+            deadlines.push(timer.ends);
+            deadlines.push(now + Duration::from_nanos(remaining.subsec_nanos().into()));
+        }
+
+        if self.dirty {
+            deadlines.extend(self.retry_at);
         }
+
+        deadlines.into_iter().min()
     }
 }
 
@@ -312,37 +921,478 @@ struct ColorForm {
 
 type SharedLights = Arc<Mutex<Lights>>;
 
-#[get("/color")]
-async fn get_color(lights: &State<SharedLights>) -> Json<Color> {
-    Json(lights.lock().await.get())
+struct Zones {
+    default: String,
+    lights: BTreeMap<String, SharedLights>,
 }
 
-#[put("/color", data = "<color>")]
-async fn set_color(color: Json<Color>, lights: &State<SharedLights>) -> Status {
-    lights.lock().await.set(*color);
+impl Zones {
+    fn get(&self, name: &str) -> Option<&SharedLights> {
+        self.lights.get(name)
+    }
 
-    Status::NoContent
+    fn default_zone(&self) -> &SharedLights {
+        &self.lights[&self.default]
+    }
 }
 
-#[get("/pattern")]
-async fn get_pattern(lights: &State<SharedLights>) -> Json<Pattern> {
-    Json(lights.lock().await.get_pattern().clone())
-}
+type SharedZones = Arc<Zones>;
 
-#[put("/pattern", data = "<pattern>")]
-async fn set_pattern(pattern: Json<Pattern>, lights: &State<SharedLights>) -> Status {
-    lights.lock().await.set_pattern(&pattern);
+type SimulatedLogs = BTreeMap<String, SimulatedLog>;
 
-    Status::NoContent
-}
+type Presets = Collection<Pattern>;
 
-#[get("/wsinfo")]
-async fn ws_info() -> String {
-    match env::var("WS_INFO") {
-        Ok(val) => val,
-        Err(_err) => String::from(""),
-    }
-}
+type SharedPresets = Arc<Presets>;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ZoneList {
+    default: String,
+    zones: Vec<String>,
+}
+
+#[get("/zones")]
+async fn get_zones(zones: &State<SharedZones>) -> Json<ZoneList> {
+    Json(ZoneList {
+        default: zones.default.clone(),
+        zones: zones.lights.keys().cloned().collect(),
+    })
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct StatusReport {
+    status: HealthStatus,
+    zones: BTreeMap<String, Health>,
+}
+
+#[get("/status")]
+async fn get_status(zones: &State<SharedZones>) -> (Status, Json<StatusReport>) {
+    let mut report = StatusReport {
+        status: HealthStatus::Ok,
+        zones: BTreeMap::new(),
+    };
+
+    for (name, lights) in zones.lights.iter() {
+        let health = lights.lock().await.health();
+
+        if health.status == HealthStatus::Failing {
+            report.status = HealthStatus::Failing;
+        }
+
+        report.zones.insert(name.clone(), health);
+    }
+
+    let status = match report.status {
+        HealthStatus::Ok => Status::Ok,
+        HealthStatus::Failing => Status::ServiceUnavailable,
+    };
+
+    (status, Json(report))
+}
+
+#[get("/zones/<zone>/status")]
+async fn get_zone_status(zone: &str, zones: &State<SharedZones>) -> Option<Json<Health>> {
+    Some(Json(zones.get(zone)?.lock().await.health()))
+}
+
+#[get("/color")]
+async fn get_color(zones: &State<SharedZones>) -> Json<Color> {
+    Json(zones.default_zone().lock().await.get())
+}
+
+#[put("/color?<transition>", data = "<color>")]
+async fn set_color(
+    color: Json<Color>,
+    transition: Option<u64>,
+    zones: &State<SharedZones>,
+) -> Status {
+    zones
+        .default_zone()
+        .lock()
+        .await
+        .set(*color, transition.map(Duration::from_millis));
+
+    Status::NoContent
+}
+
+#[get("/pattern")]
+async fn get_pattern(zones: &State<SharedZones>) -> Json<Pattern> {
+    Json(zones.default_zone().lock().await.get_pattern().clone())
+}
+
+#[put("/pattern?<transition>", data = "<pattern>")]
+async fn set_pattern(
+    pattern: Json<Pattern>,
+    transition: Option<u64>,
+    zones: &State<SharedZones>,
+) -> Status {
+    zones
+        .default_zone()
+        .lock()
+        .await
+        .set_pattern(&pattern, transition.map(Duration::from_millis));
+
+    Status::NoContent
+}
+
+#[get("/brightness")]
+async fn get_brightness(zones: &State<SharedZones>) -> Json<Brightness> {
+    Json(Brightness {
+        brightness: zones.default_zone().lock().await.get_brightness(),
+    })
+}
+
+#[put("/brightness", data = "<brightness>")]
+async fn set_brightness(brightness: Json<Brightness>, zones: &State<SharedZones>) -> Status {
+    zones
+        .default_zone()
+        .lock()
+        .await
+        .set_brightness(brightness.brightness);
+
+    Status::NoContent
+}
+
+#[get("/zones/<zone>/color")]
+async fn get_zone_color(zone: &str, zones: &State<SharedZones>) -> Option<Json<Color>> {
+    Some(Json(zones.get(zone)?.lock().await.get()))
+}
+
+#[put("/zones/<zone>/color?<transition>", data = "<color>")]
+async fn set_zone_color(
+    zone: &str,
+    color: Json<Color>,
+    transition: Option<u64>,
+    zones: &State<SharedZones>,
+) -> Option<Status> {
+    zones
+        .get(zone)?
+        .lock()
+        .await
+        .set(*color, transition.map(Duration::from_millis));
+
+    Some(Status::NoContent)
+}
+
+#[get("/zones/<zone>/pattern")]
+async fn get_zone_pattern(zone: &str, zones: &State<SharedZones>) -> Option<Json<Pattern>> {
+    Some(Json(zones.get(zone)?.lock().await.get_pattern().clone()))
+}
+
+#[put("/zones/<zone>/pattern?<transition>", data = "<pattern>")]
+async fn set_zone_pattern(
+    zone: &str,
+    pattern: Json<Pattern>,
+    transition: Option<u64>,
+    zones: &State<SharedZones>,
+) -> Option<Status> {
+    zones
+        .get(zone)?
+        .lock()
+        .await
+        .set_pattern(&pattern, transition.map(Duration::from_millis));
+
+    Some(Status::NoContent)
+}
+
+#[get("/zones/<zone>/brightness")]
+async fn get_zone_brightness(zone: &str, zones: &State<SharedZones>) -> Option<Json<Brightness>> {
+    Some(Json(Brightness {
+        brightness: zones.get(zone)?.lock().await.get_brightness(),
+    }))
+}
+
+#[put("/zones/<zone>/brightness", data = "<brightness>")]
+async fn set_zone_brightness(
+    zone: &str,
+    brightness: Json<Brightness>,
+    zones: &State<SharedZones>,
+) -> Option<Status> {
+    zones
+        .get(zone)?
+        .lock()
+        .await
+        .set_brightness(brightness.brightness);
+
+    Some(Status::NoContent)
+}
+
+#[get("/simulated")]
+async fn get_simulated(
+    zones: &State<SharedZones>,
+    simulated: &State<SimulatedLogs>,
+) -> Option<Json<Vec<SimulatedWrite>>> {
+    get_zone_simulated(&zones.default, simulated).await
+}
+
+#[delete("/simulated")]
+async fn clear_simulated(
+    zones: &State<SharedZones>,
+    simulated: &State<SimulatedLogs>,
+) -> Option<Status> {
+    clear_zone_simulated(&zones.default, simulated).await
+}
+
+#[get("/zones/<zone>/simulated")]
+async fn get_zone_simulated(
+    zone: &str,
+    simulated: &State<SimulatedLogs>,
+) -> Option<Json<Vec<SimulatedWrite>>> {
+    let writes = simulated
+        .get(zone)?
+        .lock()
+        .unwrap()
+        .iter()
+        .cloned()
+        .collect();
+
+    Some(Json(writes))
+}
+
+#[delete("/zones/<zone>/simulated")]
+async fn clear_zone_simulated(zone: &str, simulated: &State<SimulatedLogs>) -> Option<Status> {
+    simulated.get(zone)?.lock().unwrap().clear();
+
+    Some(Status::NoContent)
+}
+
+#[get("/presets")]
+async fn get_presets(presets: &State<SharedPresets>) -> Json<BTreeMap<String, Pattern>> {
+    Json(presets.all().await)
+}
+
+#[get("/presets/<name>")]
+async fn get_preset(name: &str, presets: &State<SharedPresets>) -> Option<Json<Pattern>> {
+    Some(Json(presets.get(name).await?))
+}
+
+#[put("/presets/<name>", data = "<pattern>")]
+async fn set_preset(name: &str, pattern: Json<Pattern>, presets: &State<SharedPresets>) -> Status {
+    if !valid_name(name) {
+        return Status::BadRequest;
+    }
+
+    match presets.insert(name.to_string(), pattern.into_inner()).await {
+        Ok(()) => Status::NoContent,
+        Err(err) => {
+            eprintln!("Failed to save preset {}: {}", name, err);
+            Status::InternalServerError
+        }
+    }
+}
+
+#[delete("/presets/<name>")]
+async fn delete_preset(name: &str, presets: &State<SharedPresets>) -> Status {
+    match presets.remove(name).await {
+        Ok(Some(_)) => Status::NoContent,
+        Ok(None) => Status::NotFound,
+        Err(err) => {
+            eprintln!("Failed to delete preset {}: {}", name, err);
+            Status::InternalServerError
+        }
+    }
+}
+
+#[post("/presets/<name>/activate?<zone>&<transition>")]
+async fn activate_preset(
+    name: &str,
+    zone: Option<&str>,
+    transition: Option<u64>,
+    zones: &State<SharedZones>,
+    presets: &State<SharedPresets>,
+) -> Option<Status> {
+    let pattern = presets.get(name).await?;
+
+    let lights = match zone {
+        Some(zone) => zones.get(zone)?,
+        None => zones.default_zone(),
+    };
+
+    lights
+        .lock()
+        .await
+        .set_pattern(&pattern, transition.map(Duration::from_millis));
+
+    Some(Status::NoContent)
+}
+
+#[get("/schedules")]
+async fn get_schedules(schedules: &State<SharedSchedules>) -> Json<BTreeMap<String, Schedule>> {
+    Json(schedules.all().await)
+}
+
+#[get("/schedules/<name>")]
+async fn get_schedule(name: &str, schedules: &State<SharedSchedules>) -> Option<Json<Schedule>> {
+    Some(Json(schedules.get(name).await?))
+}
+
+#[put("/schedules/<name>", data = "<schedule>")]
+async fn set_schedule(
+    name: &str,
+    schedule: Json<Schedule>,
+    zones: &State<SharedZones>,
+    schedules: &State<SharedSchedules>,
+    location: &State<Option<Location>>,
+) -> Result<Status, (Status, Json<APIError>)> {
+    let invalid = |message: String| {
+        (
+            Status::BadRequest,
+            Json(APIError {
+                status: String::from("error"),
+                message,
+            }),
+        )
+    };
+
+    if !valid_name(name) {
+        return Err(invalid(String::from(
+            "Schedule names may only contain letters, digits, '-' and '_'",
+        )));
+    }
+
+    schedule
+        .validate(zones, location.as_ref())
+        .map_err(invalid)?;
+
+    match schedules
+        .insert(name.to_string(), schedule.into_inner())
+        .await
+    {
+        Ok(()) => Ok(Status::NoContent),
+        Err(err) => {
+            eprintln!("Failed to save schedule {}: {}", name, err);
+            Ok(Status::InternalServerError)
+        }
+    }
+}
+
+#[delete("/schedules/<name>")]
+async fn delete_schedule(name: &str, schedules: &State<SharedSchedules>) -> Status {
+    match schedules.remove(name).await {
+        Ok(Some(_)) => Status::NoContent,
+        Ok(None) => Status::NotFound,
+        Err(err) => {
+            eprintln!("Failed to delete schedule {}: {}", name, err);
+            Status::InternalServerError
+        }
+    }
+}
+
+fn default_timer_fade() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct TimerRequest {
+    #[serde_as(as = "DurationMilliSeconds")]
+    duration: Duration,
+    #[serde_as(as = "DurationMilliSeconds")]
+    #[serde(default = "default_timer_fade")]
+    fade: Duration,
+    zone: Option<String>,
+}
+
+#[get("/timer?<zone>")]
+async fn get_timer(zone: Option<&str>, zones: &State<SharedZones>) -> Option<Json<TimerStatus>> {
+    let lights = zones.get(zone.unwrap_or(&zones.default))?;
+    let timer = lights.lock().await.timer()?;
+
+    Some(Json(timer))
+}
+
+#[post("/timer", data = "<request>")]
+async fn set_timer(
+    request: Json<TimerRequest>,
+    zones: &State<SharedZones>,
+) -> Option<Json<TimerStatus>> {
+    let lights = zones.get(request.zone.as_deref().unwrap_or(&zones.default))?;
+    let mut lights = lights.lock().await;
+
+    lights.set_timer(request.duration, request.fade);
+
+    Some(Json(lights.timer()?))
+}
+
+#[delete("/timer?<zone>")]
+async fn cancel_timer(zone: Option<&str>, zones: &State<SharedZones>) -> Status {
+    match zones.get(zone.unwrap_or(&zones.default)) {
+        Some(lights) if lights.lock().await.cancel_timer() => Status::NoContent,
+        _ => Status::NotFound,
+    }
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct AlarmRequest {
+    time: TimeOfDay,
+    #[serde_as(as = "DurationMilliSeconds")]
+    #[serde(default = "default_wake_duration")]
+    duration: Duration,
+    zone: Option<String>,
+}
+
+#[get("/alarm?<zone>")]
+async fn get_alarm(
+    zone: Option<&str>,
+    zones: &State<SharedZones>,
+    alarms: &State<SharedAlarms>,
+) -> Option<Json<Alarm>> {
+    Some(Json(alarms.get(zone.unwrap_or(&zones.default)).await?))
+}
+
+#[post("/alarm", data = "<request>")]
+async fn set_alarm(
+    request: Json<AlarmRequest>,
+    zones: &State<SharedZones>,
+    alarms: &State<SharedAlarms>,
+) -> Result<Json<Alarm>, Status> {
+    let zone = request.zone.as_deref().unwrap_or(&zones.default);
+
+    if zones.get(zone).is_none() {
+        return Err(Status::NotFound);
+    }
+
+    let alarm = Alarm::new(request.time, request.duration).ok_or(Status::BadRequest)?;
+
+    match alarms.insert(zone.to_string(), alarm.clone()).await {
+        Ok(()) => Ok(Json(alarm)),
+        Err(err) => {
+            eprintln!("Failed to save alarm for zone {}: {}", zone, err);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[delete("/alarm?<zone>")]
+async fn cancel_alarm(
+    zone: Option<&str>,
+    zones: &State<SharedZones>,
+    alarms: &State<SharedAlarms>,
+) -> Status {
+    let zone = zone.unwrap_or(&zones.default);
+
+    match alarm::cancel(zones, alarms, zone).await {
+        Ok(true) => Status::NoContent,
+        Ok(false) => Status::NotFound,
+        Err(err) => {
+            eprintln!("Failed to cancel alarm for zone {}: {}", zone, err);
+            Status::InternalServerError
+        }
+    }
+}
+
+// defaults to the in-band /ws route on the host the client connected to
+#[get("/wsinfo")]
+async fn ws_info(uri: Option<WSUri>) -> String {
+    env::var("WS_INFO").unwrap_or_else(|_err| match uri {
+        Some(WSUri(uri)) => uri,
+        None => String::new(),
+    })
+}
 
 #[get("/static/<file..>")]
 async fn files(file: PathBuf) -> Option<NamedFile> {
@@ -364,8 +1414,11 @@ async fn manifest() -> Option<NamedFile> {
 }
 
 #[get("/")]
-async fn form(lights: &State<SharedLights>) -> Template {
-    let context = [(String::from("color"), lights.lock().await.get().to_string())];
+async fn form(zones: &State<SharedZones>) -> Template {
+    let context = [(
+        String::from("color"),
+        zones.default_zone().lock().await.get().to_string(),
+    )];
 
     Template::render(
         "form",
@@ -374,8 +1427,12 @@ async fn form(lights: &State<SharedLights>) -> Template {
 }
 
 #[post("/", data = "<color_form>")]
-async fn form_submit(color_form: Form<ColorForm>, lights: &State<SharedLights>) -> Redirect {
-    lights.lock().await.set(color_form.color);
+async fn form_submit(color_form: Form<ColorForm>, zones: &State<SharedZones>) -> Redirect {
+    zones
+        .default_zone()
+        .lock()
+        .await
+        .set(color_form.color, None);
 
     Redirect::to(uri!(form))
 }
@@ -396,155 +1453,660 @@ async fn unprocessable_entity() -> Json<APIError> {
     })
 }
 
-#[catch(404)]
-async fn not_found() -> Json<APIError> {
-    Json(APIError {
-        status: String::from("error"),
-        message: String::from("Resource not found"),
-    })
+#[catch(404)]
+async fn not_found() -> Json<APIError> {
+    Json(APIError {
+        status: String::from("error"),
+        message: String::from("Resource not found"),
+    })
+}
+
+#[catch(500)]
+async fn internal_server_error() -> Json<APIError> {
+    Json(APIError {
+        status: String::from("error"),
+        message: String::from("Internal server error"),
+    })
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct ColorCommand {
+    #[serde(flatten)]
+    color: Color,
+    #[serde_as(as = "Option<DurationMilliSeconds>")]
+    #[serde(default)]
+    transition: Option<Duration>,
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct PresetCommand {
+    preset: String,
+    #[serde_as(as = "Option<DurationMilliSeconds>")]
+    #[serde(default)]
+    transition: Option<Duration>,
+}
+
+#[serde_as]
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde")]
+struct PatternCommand {
+    pattern: Pattern,
+    #[serde_as(as = "Option<DurationMilliSeconds>")]
+    #[serde(default)]
+    transition: Option<Duration>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+enum WSCommand {
+    SetColor(ColorCommand),
+    SetPattern(PatternCommand),
+    SetBrightness(Brightness),
+    ActivatePreset(PresetCommand),
+    GetState,
+}
+
+// messages without a type from clients that predate the envelope
+#[derive(Deserialize)]
+#[serde(crate = "rocket::serde", untagged)]
+enum WSBareCommand {
+    Color(ColorCommand),
+    Brightness(Brightness),
+    Preset(PresetCommand),
+}
+
+impl From<WSBareCommand> for WSCommand {
+    fn from(command: WSBareCommand) -> WSCommand {
+        match command {
+            WSBareCommand::Color(command) => WSCommand::SetColor(command),
+            WSBareCommand::Brightness(brightness) => WSCommand::SetBrightness(brightness),
+            WSBareCommand::Preset(command) => WSCommand::ActivatePreset(command),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+enum WSUpdate<'a> {
+    Color(Color),
+    State {
+        pattern: &'a Pattern,
+        brightness: u8,
+        timer: Option<TimerStatus>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+fn ws_zone(zones: &Zones, path: &str) -> Option<String> {
+    match path.trim_end_matches('/') {
+        "" => Some(zones.default.clone()),
+        path => {
+            let name = path.strip_prefix("/zones/")?;
+
+            zones.get(name).map(|_| String::from(name))
+        }
+    }
+}
+
+/// Key of a WebSocket handshake request, used to accept the upgrade
+struct WSKey(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WSKey {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let headers = request.headers();
+
+        let upgrade = headers
+            .get_one("Upgrade")
+            .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+
+        match headers.get_one("Sec-WebSocket-Key") {
+            Some(key) if upgrade => Outcome::Success(WSKey(String::from(key))),
+            _ => Outcome::Error((Status::BadRequest, ())),
+        }
+    }
+}
+
+/// URI of the in-band WebSocket on the host the client connected to, using
+/// `wss` behind TLS or a proxy reporting `X-Forwarded-Proto: https`
+struct WSUri(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WSUri {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        // proxies chaining several hops list the client-facing one first
+        let forwarded_https = request
+            .headers()
+            .get_one("X-Forwarded-Proto")
+            .and_then(|proto| proto.split(',').next())
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"));
+
+        let scheme = if request.rocket().config().tls_enabled() || forwarded_https {
+            "wss"
+        } else {
+            "ws"
+        };
+
+        match request.host() {
+            Some(host) => Outcome::Success(WSUri(format!("{}://{}/ws", scheme, host))),
+            None => Outcome::Error((Status::BadRequest, ())),
+        }
+    }
+}
+
+/// Switches a request over to the WebSocket protocol for a zone
+struct WSUpgrade {
+    key: WSKey,
+    lights: SharedLights,
+    presets: SharedPresets,
+}
+
+impl<'r> Responder<'r, 'static> for WSUpgrade {
+    fn respond_to(self, _request: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .raw_header(
+                "Sec-WebSocket-Accept",
+                derive_accept_key(self.key.0.as_bytes()),
+            )
+            .upgrade("websocket", self)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl IoHandler for WSUpgrade {
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> IoResult<()> {
+        let upgrade = Pin::into_inner(self);
+        let stream = WebSocketStream::from_raw_socket(io, WSRole::Server, None).await;
+
+        ws_connection(stream, upgrade.lights, upgrade.presets).await;
+
+        Ok(())
+    }
+}
+
+#[get("/ws")]
+async fn ws(key: WSKey, zones: &State<SharedZones>, presets: &State<SharedPresets>) -> WSUpgrade {
+    WSUpgrade {
+        key,
+        lights: Arc::clone(zones.default_zone()),
+        presets: Arc::clone(presets),
+    }
+}
+
+#[get("/zones/<zone>/ws")]
+async fn zone_ws(
+    zone: &str,
+    key: WSKey,
+    zones: &State<SharedZones>,
+    presets: &State<SharedPresets>,
+) -> Option<WSUpgrade> {
+    Some(WSUpgrade {
+        key,
+        lights: Arc::clone(zones.get(zone)?),
+        presets: Arc::clone(presets),
+    })
+}
+
+/// Standalone WebSocket listener on its own port, for clients that predate
+/// the `/ws` route
+async fn ws_server(zones: SharedZones, presets: SharedPresets, port: u16) {
+    let address = match env::var("WS_ADDRESS") {
+        Ok(val) => val,
+        Err(_err) => String::from(if cfg!(debug_assertions) {
+            "127.0.0.1"
+        } else {
+            "0.0.0.0"
+        }),
+    };
+
+    let listener = TcpListener::bind((address, port))
+        .await
+        .expect("Failed to bind TCP WebSocket address");
+
+    println!(
+        "{}{} {}",
+        Paint::masked("🕸  "),
+        Paint::default("WebSocket server started on").bold(),
+        Paint::default(String::from("ws://") + &listener.local_addr().unwrap().to_string())
+            .bold()
+            .underline()
+    );
+
+    loop {
+        let socket = match listener.accept().await {
+            Ok((socket, _)) => socket,
+            Err(err) => {
+                eprintln!("Failed to accept WebSocket connection: {}", err);
+                continue;
+            }
+        };
+
+        // handshakes run on their own task so a slow client cannot hold up
+        // accepting others
+        tokio::spawn(ws_accept(socket, Arc::clone(&zones), Arc::clone(&presets)));
+    }
+}
+
+async fn ws_accept(socket: TcpStream, zones: SharedZones, presets: SharedPresets) {
+    let mut zone = None;
+
+    // the request path selects the zone, e.g. /zones/<name>
+    #[allow(clippy::result_large_err)]
+    let callback = |request: &WSRequest, response: WSResponse| {
+        let path = request.uri().path();
+
+        match ws_zone(&zones, path) {
+            Some(name) => {
+                zone = Some(name);
+
+                Ok(response)
+            }
+            None => {
+                let mut error = WSErrorResponse::new(Some(String::from("Unknown zone")));
+                *error.status_mut() = WSStatusCode::NOT_FOUND;
+
+                Err(error)
+            }
+        }
+    };
+
+    let stream = match tokio_tungstenite::accept_hdr_async(socket, callback).await {
+        Ok(stream) => stream,
+        Err(err) => {
+            eprintln!("Failed to accept WebSocket connection: {}", err);
+            return;
+        }
+    };
+
+    let lights = Arc::clone(&zones.lights[&zone.unwrap()]);
+
+    ws_connection(stream, lights, presets).await;
+}
+
+// replies to a client's own messages that can be waiting to be sent before
+// the client is considered too far behind
+const WS_QUEUE: usize = 16;
+// longest a single message may take to send before the client is dropped
+const WS_SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+enum WSReply {
+    Error(String),
+    Resync,
+}
+
+/// Apply commands from a WebSocket client until it disconnects, while a
+/// separate writer streams state updates to it
+async fn ws_connection<S>(stream: WebSocketStream<S>, lights: SharedLights, presets: SharedPresets)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, mut receiver) = stream.split();
+
+    let state = lights.lock().await.subscribe();
+    let (replies, queue) = mpsc::channel(WS_QUEUE);
+
+    // new clients start with the whole state
+    let _ = replies.try_send(WSReply::Resync);
+
+    let mut writer = tokio::spawn(ws_writer(sender, state, queue));
+
+    loop {
+        tokio::select! {
+            message = receiver.next() => match message {
+                Some(Ok(WSMessage::Text(string))) => {
+                    let reply = match ws_parse(&string) {
+                        Ok(WSCommand::GetState) => Some(WSReply::Resync),
+                        Ok(command) => ws_command(&lights, &presets, command)
+                            .await
+                            .err()
+                            .map(WSReply::Error),
+                        Err(err) => Some(WSReply::Error(err)),
+                    };
+
+                    if let Some(reply) = reply {
+                        if let Err(err) = replies.try_send(reply) {
+                            if let TrySendError::Full(_) = err {
+                                eprintln!("WebSocket client fell too far behind, disconnecting");
+                            }
+
+                            break;
+                        }
+                    }
+                }
+                Some(Ok(WSMessage::Close(_frame))) => {
+                    break;
+                }
+                Some(Ok(_)) => {
+                    // ignore other message types
+                }
+                Some(Err(WSError::Protocol(
+                    WSProtocolError::ResetWithoutClosingHandshake,
+                ))) => {
+                    // resets seem to be common for browsers
+                    break;
+                }
+                Some(Err(err)) => {
+                    eprintln!("Failed to poll WebSocket connection: {}", err);
+                    break;
+                }
+                None => {
+                    break;
+                }
+            },
+            _ = &mut writer => {
+                // the writer gave up on the client
+                return;
+            }
+        }
+    }
+
+    // closing the queue tells the writer to close the connection
+    drop(replies);
+
+    if let Err(err) = writer.await {
+        eprintln!("WebSocket writer failed: {}", err);
+    }
+}
+
+/// Send state updates and replies to a WebSocket client until the queue is
+/// closed or the client stops keeping up. State updates are taken from the
+/// watch channel only when the client is ready for them, so a slow client
+/// skips straight to the latest state instead of building up a backlog.
+async fn ws_writer<S>(
+    mut sender: SplitSink<WebSocketStream<S>, WSMessage>,
+    mut state: watch::Receiver<LightState>,
+    mut queue: mpsc::Receiver<WSReply>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let mut last: Option<LightState> = None;
+
+    loop {
+        let strings = tokio::select! {
+            reply = queue.recv() => match reply {
+                Some(WSReply::Error(message)) => {
+                    vec![serde_json::to_string(&WSUpdate::Error { message }).unwrap()]
+                }
+                Some(WSReply::Resync) => {
+                    let current = state.borrow_and_update().clone();
+                    let strings = ws_updates(None, &current);
+                    last = Some(current);
+
+                    strings
+                }
+                None => break,
+            },
+            changed = state.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+
+                let current = state.borrow_and_update().clone();
+                let strings = ws_updates(last.as_ref(), &current);
+                last = Some(current);
+
+                strings
+            }
+        };
+
+        for string in strings {
+            match time::timeout(WS_SEND_TIMEOUT, sender.send(WSMessage::Text(string))).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    eprintln!("Failed to send update to WebSocket: {}", err);
+                    return;
+                }
+                Err(_elapsed) => {
+                    eprintln!("WebSocket client fell too far behind, disconnecting");
+                    return;
+                }
+            }
+        }
+    }
+
+    match time::timeout(WS_SEND_TIMEOUT, sender.close()).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => {
+            eprintln!("Failed to close WebSocket connection: {}", err);
+        }
+        Err(_elapsed) => {
+            eprintln!("Timed out closing WebSocket connection");
+        }
+    }
+}
+
+/// Messages for everything that changed since the last state sent to a
+/// WebSocket client, or the whole state for a new client
+fn ws_updates(last: Option<&LightState>, state: &LightState) -> Vec<String> {
+    let mut strings = Vec::new();
+
+    if last.map(|last| last.color) != Some(state.color) {
+        strings.push(serde_json::to_string(&WSUpdate::Color(state.color)).unwrap());
+    }
+
+    let changed = match last {
+        Some(last) => last.revision != state.revision || last.timer != state.timer,
+        None => true,
+    };
+
+    if changed {
+        strings.push(
+            serde_json::to_string(&WSUpdate::State {
+                pattern: &state.pattern,
+                brightness: state.brightness,
+                timer: state.timer,
+            })
+            .unwrap(),
+        );
+    }
+
+    strings
 }
 
-async fn ws_server(lights: SharedLights, chronon: Duration) {
-    let address = match env::var("WS_ADDRESS") {
-        Ok(val) => val,
-        Err(_err) => String::from(if cfg!(debug_assertions) {
-            "127.0.0.1"
-        } else {
-            "0.0.0.0"
-        }),
-    };
+fn ws_parse(string: &str) -> Result<WSCommand, String> {
+    let value: Value = serde_json::from_str(string).map_err(|err| err.to_string())?;
 
-    let port: u16 = match env::var("WS_PORT") {
-        Ok(val) => val.parse().unwrap(),
-        Err(_err) => 8001,
+    let command = if value.get("type").is_some() {
+        serde_json::from_value(value)
+    } else {
+        serde_json::from_value::<WSBareCommand>(value).map(WSCommand::from)
     };
 
-    let listener = TcpListener::bind((address, port))
-        .await
-        .expect("Failed to bind TCP WebSocket address");
+    command.map_err(|err| err.to_string())
+}
 
-    println!(
-        "{}{} {}",
-        Paint::masked("🕸  "),
-        Paint::default("WebSocket server started on").bold(),
-        Paint::default(String::from("ws://") + &listener.local_addr().unwrap().to_string())
-            .bold()
-            .underline()
-    );
+async fn ws_command(
+    lights: &SharedLights,
+    presets: &Presets,
+    command: WSCommand,
+) -> Result<(), String> {
+    match command {
+        WSCommand::SetColor(command) => {
+            lights.lock().await.set(command.color, command.transition);
+        }
+        WSCommand::SetPattern(command) => {
+            lights
+                .lock()
+                .await
+                .set_pattern(&command.pattern, command.transition);
+        }
+        WSCommand::SetBrightness(brightness) => {
+            lights.lock().await.set_brightness(brightness.brightness);
+        }
+        WSCommand::ActivatePreset(command) => match presets.get(&command.preset).await {
+            Some(pattern) => {
+                lights
+                    .lock()
+                    .await
+                    .set_pattern(&pattern, command.transition);
+            }
+            None => return Err(format!("Unknown preset {}", command.preset)),
+        },
+        WSCommand::GetState => {}
+    }
 
-    let streams = Arc::new(Mutex::new(HashMap::<
-        SocketAddr,
-        SplitSink<WebSocketStream<TcpStream>, WSMessage>,
-    >::new()));
+    Ok(())
+}
 
-    let mut last_color = lights.lock().await.get();
+/// Split an OSC address into the zone it addresses and the command, e.g.
+/// `/zone/<name>/color`, with unprefixed addresses going to the default zone
+fn osc_zone<'a>(zones: &'a Zones, addr: &'a str) -> Option<(&'a str, &'a SharedLights, &'a str)> {
+    match addr.strip_prefix("/zone/") {
+        Some(rest) => {
+            let (name, command) = rest.split_at(rest.find('/')?);
 
-    let mut interval = time::interval(chronon);
+            Some((name, zones.get(name)?, command))
+        }
+        None => Some((&zones.default, zones.default_zone(), addr)),
+    }
+}
 
-    loop {
-        tokio::select! {
-            connection = listener.accept() => {
-                match connection {
-                    Ok((socket, _)) => {
-                        match tokio_tungstenite::accept_async(socket).await {
-                            Ok(stream) => {
-                                let peer = stream.get_ref().peer_addr().unwrap();
-
-                                let (mut sender, mut receiver) = stream.split();
-
-                                match sender.send(WSMessage::Text(serde_json::to_string(&lights.lock().await.get()).unwrap())).await {
-                                    Ok(_) => {},
-                                    Err(err) => {
-                                        // task should handle removal on I/O errors
-                                        eprintln!("Failed to send color to WebSocket: {}", err);
-                                    }
-                                }
+/// Parse a color given as four RGBW numbers or an OSC color, returning the
+/// remaining arguments
+fn osc_color(args: &[OscType]) -> Option<(Color, &[OscType])> {
+    match args {
+        [OscType::Int(red), OscType::Int(green), OscType::Int(blue), OscType::Int(white), rest @ ..] => {
+            Some((
+                Color {
+                    red: *red as u8,
+                    green: *green as u8,
+                    blue: *blue as u8,
+                    white: *white as u8,
+                },
+                rest,
+            ))
+        }
+        [OscType::Float(red), OscType::Float(green), OscType::Float(blue), OscType::Float(white), rest @ ..] => {
+            Some((
+                Color {
+                    red: *red as u8,
+                    green: *green as u8,
+                    blue: *blue as u8,
+                    white: *white as u8,
+                },
+                rest,
+            ))
+        }
+        [OscType::Double(red), OscType::Double(green), OscType::Double(blue), OscType::Double(white), rest @ ..] => {
+            Some((
+                Color {
+                    red: *red as u8,
+                    green: *green as u8,
+                    blue: *blue as u8,
+                    white: *white as u8,
+                },
+                rest,
+            ))
+        }
+        [OscType::Color(color), rest @ ..] => Some((
+            Color {
+                red: color.red,
+                green: color.green,
+                blue: color.blue,
+                white: color.alpha,
+            },
+            rest,
+        )),
+        _ => None,
+    }
+}
 
-                                streams.lock().await.insert(peer, sender);
-
-                                let lights_conn = Arc::clone(&lights);
-                                let streams_conn = Arc::clone(&streams);
-
-                                tokio::spawn(async move {
-                                    loop {
-                                        match receiver.next().await {
-                                            Some(Ok(WSMessage::Text(string))) => {
-                                                match serde_json::from_str::<Color>(&string) {
-                                                    Ok(color) => {
-                                                        lights_conn.lock().await.set(color);
-                                                    },
-                                                    Err(err) => {
-                                                        eprintln!("Failed to parse color from WebSocket: {}", err);
-                                                    }
-                                                }
-                                            },
-                                            Some(Ok(WSMessage::Close(_frame))) => {
-                                                break;
-                                            },
-                                            Some(Ok(_)) => {
-                                                // ignore other message types
-                                            },
-                                            Some(Err(WSError::Protocol(WSProtocolError::ResetWithoutClosingHandshake))) => {
-                                                // resets seem to be common for browsers
-                                                break;
-                                            },
-                                            Some(Err(err)) => {
-                                                eprintln!("Failed to poll WebSocket connection: {}", err);
-                                                break;
-                                            },
-                                            None => {
-                                                break;
-                                            }
-                                        }
-                                    }
+fn osc_number(args: &[OscType]) -> Option<(f64, &[OscType])> {
+    match args {
+        [OscType::Int(value), rest @ ..] => Some((*value as f64, rest)),
+        [OscType::Float(value), rest @ ..] => Some((*value as f64, rest)),
+        [OscType::Double(value), rest @ ..] => Some((*value, rest)),
+        _ => None,
+    }
+}
 
-                                    let removed = streams_conn.lock().await.remove(&peer);
+/// Parse a time in milliseconds, returning the remaining arguments
+fn osc_duration(args: &[OscType]) -> Option<(Duration, &[OscType])> {
+    let (millis, rest) = osc_number(args)?;
 
-                                    if let Some(mut stream) = removed {
-                                        match stream.close().await {
-                                            Ok(()) => {},
-                                            Err(err) => {
-                                                eprintln!("Failed to close WebSocket connection: {}", err);
-                                            }
-                                        }
-                                    }
-                                });
-                            },
-                            Err(err) => {
-                                eprintln!("Failed to accept WebSocket connection: {}", err);
-                            }
-                        }
-                    },
-                    Err(err) => {
-                        eprintln!("Failed to accept WebSocket connection: {}", err);
-                    }
-                }
-            }
+    Some((Duration::try_from_secs_f64(millis / 1000.0).ok()?, rest))
+}
 
-            _ = interval.tick() => {
-                let color = lights.lock().await.get();
+/// Parse an optional trailing transition time in milliseconds
+fn osc_transition(args: &[OscType]) -> Option<Option<Duration>> {
+    match args {
+        [] => Some(None),
+        args => match osc_duration(args)? {
+            (transition, []) => Some(Some(transition)),
+            _ => None,
+        },
+    }
+}
 
-                if color != last_color {
-                    let string = serde_json::to_string(&color).unwrap();
+fn osc_color_args(args: &[OscType]) -> Option<(Color, Option<Duration>)> {
+    let (color, rest) = osc_color(args)?;
 
-                    for (_, stream) in streams.lock().await.iter_mut() {
-                        match stream.send(WSMessage::Text(string.clone())).await {
-                            Ok(_) => {},
-                            Err(err) => {
-                                // task should handle removal on I/O errors
-                                eprintln!("Failed to send color to WebSocket: {}", err);
-                            }
-                        }
-                    }
+    Some((color, osc_transition(rest)?))
+}
 
-                    last_color = color;
-                }
-            }
+/// Parse the arguments of a `/pattern/<name>` address, which are the pattern's
+/// parameters in order followed by an optional transition
+fn osc_pattern(name: &str, args: &[OscType]) -> Option<(Pattern, Option<Duration>)> {
+    let (pattern, rest) = match name {
+        "off" => (Pattern::Off, args),
+        "solid" => {
+            let (color, rest) = osc_color(args)?;
+
+            (Pattern::Solid(color), rest)
         }
-    }
+        "rainbow" => {
+            let (period, rest) = osc_duration(args)?;
+
+            (
+                Pattern::Rainbow(Rainbow {
+                    period,
+                    spread: 0.0,
+                }),
+                rest,
+            )
+        }
+        "breathe" => {
+            let (color, rest) = osc_color(args)?;
+            let (period, rest) = osc_duration(rest)?;
+
+            (Pattern::Breathe(Breathe { color, period }), rest)
+        }
+        "strobe" => {
+            let (color, rest) = osc_color(args)?;
+            let (rate, rest) = osc_number(rest)?;
+
+            (Pattern::Strobe(Strobe { color, rate }), rest)
+        }
+        "candle" => {
+            let (color, rest) = osc_color(args)?;
+            let (intensity, rest) = osc_number(rest)?;
+
+            (Pattern::Candle(Candle { color, intensity }), rest)
+        }
+        "fire" => (Pattern::Fire, args),
+        "wake" => {
+            let (duration, rest) = osc_duration(args)?;
+
+            (Pattern::Wake(Wake { duration }), rest)
+        }
+        _ => return None,
+    };
+
+    Some((pattern, osc_transition(rest)?))
 }
 
-async fn osc_server(lights: SharedLights) {
+async fn osc_server(zones: SharedZones, presets: SharedPresets, alarms: SharedAlarms) {
     let address = match env::var("OSC_ADDRESS") {
         Ok(val) => val,
         Err(_err) => String::from(if cfg!(debug_assertions) {
@@ -578,93 +2140,127 @@ async fn osc_server(lights: SharedLights) {
         match socket.recv_from(&mut buffer).await {
             Ok((size, _addr)) => match rosc::decoder::decode_udp(&buffer[..size]) {
                 Ok(packet) => match packet {
-                    (_, OscPacket::Message(msg)) => match msg.addr.as_ref() {
-                        "/color" => match &msg.args[..] {
-                            [OscType::Int(red), OscType::Int(green), OscType::Int(blue), OscType::Int(white)] => {
-                                lights.lock().await.set(Color {
-                                    red: *red as u8,
-                                    green: *green as u8,
-                                    blue: *blue as u8,
-                                    white: *white as u8,
-                                });
-                            }
-                            [OscType::Float(red), OscType::Float(green), OscType::Float(blue), OscType::Float(white)] => {
-                                lights.lock().await.set(Color {
-                                    red: *red as u8,
-                                    green: *green as u8,
-                                    blue: *blue as u8,
-                                    white: *white as u8,
-                                });
-                            }
-                            [OscType::Double(red), OscType::Double(green), OscType::Double(blue), OscType::Double(white)] =>
-                            {
-                                lights.lock().await.set(Color {
-                                    red: *red as u8,
-                                    green: *green as u8,
-                                    blue: *blue as u8,
-                                    white: *white as u8,
-                                });
-                            }
-                            [OscType::Color(color)] => {
-                                lights.lock().await.set(Color {
-                                    red: color.red,
-                                    green: color.green,
-                                    blue: color.blue,
-                                    white: color.alpha,
-                                });
-                            }
-                            _ => {
-                                eprintln!("Unexpected OSC /color command: {:?}", msg.args);
-                            }
-                        },
-                        "/pattern/off" => match &msg.args[..] {
-                            [] => {
-                                lights.lock().await.set_pattern(&Pattern::Off);
-                            }
-                            _ => {
-                                eprintln!("Unexpected OSC /pattern/off command: {:?}", msg.args);
-                            }
-                        },
-                        "/pattern/solid" => match &msg.args[..] {
-                            [OscType::Int(red), OscType::Int(green), OscType::Int(blue), OscType::Int(white)] => {
-                                lights.lock().await.set_pattern(&Pattern::Solid(Color {
-                                    red: *red as u8,
-                                    green: *green as u8,
-                                    blue: *blue as u8,
-                                    white: *white as u8,
-                                }));
-                            }
-                            [OscType::Float(red), OscType::Float(green), OscType::Float(blue), OscType::Float(white)] => {
-                                lights.lock().await.set_pattern(&Pattern::Solid(Color {
-                                    red: *red as u8,
-                                    green: *green as u8,
-                                    blue: *blue as u8,
-                                    white: *white as u8,
-                                }));
-                            }
-                            [OscType::Double(red), OscType::Double(green), OscType::Double(blue), OscType::Double(white)] =>
-                            {
-                                lights.lock().await.set_pattern(&Pattern::Solid(Color {
-                                    red: *red as u8,
-                                    green: *green as u8,
-                                    blue: *blue as u8,
-                                    white: *white as u8,
-                                }));
-                            }
-                            [OscType::Color(color)] => {
-                                lights.lock().await.set_pattern(&Pattern::Solid(Color {
-                                    red: color.red,
-                                    green: color.green,
-                                    blue: color.blue,
-                                    white: color.alpha,
-                                }));
+                    (_, OscPacket::Message(msg)) => match osc_zone(&zones, &msg.addr) {
+                        Some((zone, lights, command)) => match command {
+                            "/color" => match osc_color_args(&msg.args) {
+                                Some((color, transition)) => {
+                                    lights.lock().await.set(color, transition);
+                                }
+                                None => {
+                                    eprintln!("Unexpected OSC /color command: {:?}", msg.args);
+                                }
+                            },
+                            "/brightness" => match &msg.args[..] {
+                                [OscType::Int(brightness)] => {
+                                    lights.lock().await.set_brightness(*brightness as u8);
+                                }
+                                [OscType::Float(brightness)] => {
+                                    lights.lock().await.set_brightness(*brightness as u8);
+                                }
+                                [OscType::Double(brightness)] => {
+                                    lights.lock().await.set_brightness(*brightness as u8);
+                                }
+                                _ => {
+                                    eprintln!("Unexpected OSC /brightness command: {:?}", msg.args);
+                                }
+                            },
+                            "/preset" => match &msg.args[..] {
+                                [OscType::String(name), rest @ ..] => {
+                                    match (presets.get(name).await, osc_transition(rest)) {
+                                        (Some(pattern), Some(transition)) => {
+                                            lights.lock().await.set_pattern(&pattern, transition);
+                                        }
+                                        (None, _) => {
+                                            eprintln!("Unknown OSC preset: {}", name);
+                                        }
+                                        (_, None) => {
+                                            eprintln!(
+                                                "Unexpected OSC /preset command: {:?}",
+                                                msg.args
+                                            );
+                                        }
+                                    }
+                                }
+                                _ => {
+                                    eprintln!("Unexpected OSC /preset command: {:?}", msg.args);
+                                }
+                            },
+                            "/alarm" => match &msg.args[..] {
+                                [OscType::String(time), rest @ ..] => {
+                                    let duration = match rest {
+                                        [] => Some(default_wake_duration()),
+                                        rest => match osc_duration(rest) {
+                                            Some((duration, [])) => Some(duration),
+                                            _ => None,
+                                        },
+                                    };
+
+                                    let alarm = TimeOfDay::try_from(time.clone())
+                                        .ok()
+                                        .zip(duration)
+                                        .and_then(|(time, duration)| Alarm::new(time, duration));
+
+                                    match alarm {
+                                        Some(alarm) => {
+                                            if let Err(err) =
+                                                alarms.insert(zone.to_string(), alarm).await
+                                            {
+                                                eprintln!(
+                                                    "Failed to save alarm for zone {}: {}",
+                                                    zone, err
+                                                );
+                                            }
+                                        }
+                                        None => {
+                                            eprintln!(
+                                                "Unexpected OSC /alarm command: {:?}",
+                                                msg.args
+                                            );
+                                        }
+                                    }
+                                }
+                                _ => {
+                                    eprintln!("Unexpected OSC /alarm command: {:?}", msg.args);
+                                }
+                            },
+                            "/alarm/cancel" => match &msg.args[..] {
+                                [] => {
+                                    if let Err(err) = alarm::cancel(&zones, &alarms, zone).await {
+                                        eprintln!(
+                                            "Failed to cancel alarm for zone {}: {}",
+                                            zone, err
+                                        );
+                                    }
+                                }
+                                _ => {
+                                    eprintln!(
+                                        "Unexpected OSC /alarm/cancel command: {:?}",
+                                        msg.args
+                                    );
+                                }
+                            },
+                            command if command.starts_with("/pattern/") => {
+                                match osc_pattern(&command["/pattern/".len()..], &msg.args) {
+                                    Some((pattern, transition)) => {
+                                        lights.lock().await.set_pattern(&pattern, transition);
+                                    }
+                                    None => {
+                                        eprintln!(
+                                            "Unexpected OSC {} command: {:?}",
+                                            command, msg.args
+                                        );
+                                    }
+                                }
                             }
                             _ => {
-                                eprintln!("Unexpected OSC /pattern/solid command: {:?}", msg.args);
+                                eprintln!("Unexpected OSC Message: {}: {:?}", msg.addr, msg.args);
                             }
                         },
-                        _ => {
-                            eprintln!("Unexpected OSC Message: {}: {:?}", msg.addr, msg.args);
+                        None => {
+                            eprintln!(
+                                "Unexpected OSC Message for unknown zone: {}: {:?}",
+                                msg.addr, msg.args
+                            );
                         }
                     },
                     (_, OscPacket::Bundle(bundle)) => {
@@ -682,18 +2278,77 @@ async fn osc_server(lights: SharedLights) {
     }
 }
 
-async fn pattern_output(lights: SharedLights, chronon: Duration) {
+async fn pattern_output(zone: String, lights: SharedLights, chronon: Duration) {
     println!(
-        "{}{}",
+        "{}{} {}",
         Paint::masked("💡 "),
-        Paint::default("Light pattern output started").bold()
+        Paint::default("Light pattern output started for zone").bold(),
+        Paint::default(zone).bold().underline()
     );
 
-    let mut interval = time::interval(chronon);
+    let changed = lights.lock().await.changed();
 
     loop {
-        interval.tick().await;
-        lights.lock().await.tick();
+        let deadline = lights.lock().await.tick(chronon);
+
+        match deadline {
+            Some(deadline) => {
+                tokio::select! {
+                    _ = time::sleep_until(deadline) => {}
+                    _ = changed.notified() => {}
+                }
+            }
+            None => changed.notified().await,
+        }
+    }
+}
+
+fn abort(message: impl Display) -> ! {
+    eprintln!("{} {}", Paint::red("Error:").bold(), message);
+
+    process::exit(1);
+}
+
+fn open_output(zone: &str, config: &ZoneConfig) -> (Box<dyn Output>, Option<SimulatedLog>) {
+    let (output, simulated): (Box<dyn Output>, Option<SimulatedLog>) = match &config.output {
+        OutputConfig::Gpio(gpio_config) => match GpioOutput::new(gpio_config) {
+            Ok(output) => (Box::new(output), None),
+            Err(err) => abort(format!(
+                "Failed to set up GPIO output for zone {}: {}",
+                zone, err
+            )),
+        },
+        OutputConfig::Ws2812(ws2812_config) => match Ws2812Output::new(ws2812_config) {
+            Ok(output) => (Box::new(output), None),
+            Err(err) => abort(format!(
+                "Failed to set up addressable LED output for zone {}: {}",
+                zone, err
+            )),
+        },
+        OutputConfig::Simulated(simulated_config) => {
+            let output = SimulatedOutput::new(simulated_config);
+            let log = output.log();
+
+            println!(
+                "{}{} {}",
+                Paint::masked("🔦 "),
+                Paint::default("Using simulated light output for zone").bold(),
+                Paint::default(zone).bold().underline()
+            );
+
+            (Box::new(output), Some(log))
+        }
+    };
+
+    match &config.calibration {
+        Some(calibration) => match CalibratedOutput::new(output, calibration) {
+            Ok(output) => (Box::new(output), simulated),
+            Err(err) => abort(format!(
+                "Failed to set up output calibration for zone {}: {}",
+                zone, err
+            )),
+        },
+        None => (output, simulated),
     }
 }
 
@@ -707,71 +2362,405 @@ fn rocket() -> _ {
     };
 
     let chronon = Duration::from_millis(10);
+    let save_period = Duration::from_secs(1);
+    let alarm_period = Duration::from_secs(1);
 
-    let gpio = Gpio::new().unwrap();
-
-    let lights = Arc::new(Mutex::new(Lights::new(
-        Output {
-            frequency: 60.0,
-
-            red: gpio.get(17).unwrap().into_output(),
-            green: gpio.get(27).unwrap().into_output(),
-            blue: gpio.get(22).unwrap().into_output(),
-            white: gpio.get(18).unwrap().into_output(),
-        },
-        Pattern::Solid(initial),
-    )));
-
-    let lights_rocket = Arc::clone(&lights);
-    let lights_ws = Arc::clone(&lights);
-    let lights_osc = Arc::clone(&lights);
-    let lights_output = Arc::clone(&lights);
-
-    rocket::custom(Config::figment().merge((
+    let figment = Config::figment().merge((
         "address",
         (if cfg!(debug_assertions) {
             "127.0.0.1"
         } else {
             "0.0.0.0"
         }),
-    )))
-    .mount(
-        "/",
-        routes![
-            get_color,
-            set_color,
-            get_pattern,
-            set_pattern,
-            ws_info,
-            files,
-            service_worker,
-            manifest,
-            form,
-            form_submit
-        ],
-    )
-    .register("/", catchers![bad_request, unprocessable_entity, not_found])
-    .manage(lights_rocket)
-    .attach(Template::fairing())
-    .attach(AdHoc::on_liftoff("WebSocket Server", move |_rocket| {
-        Box::pin(async move {
-            tokio::spawn(async move {
-                ws_server(lights_ws, chronon).await;
-            });
-        })
-    }))
-    .attach(AdHoc::on_liftoff("OSC Server", move |_rocket| {
-        Box::pin(async move {
-            tokio::spawn(async move {
-                osc_server(lights_osc).await;
-            });
-        })
-    }))
-    .attach(AdHoc::on_liftoff("Light Pattern Output", move |_rocket| {
-        Box::pin(async move {
-            tokio::spawn(async move {
-                pattern_output(lights_output, chronon).await;
-            });
-        })
-    }))
+    ));
+
+    let config: LightsConfig = match figment.extract() {
+        Ok(config) => config,
+        Err(err) => {
+            pretty_print_error(err);
+            abort("Failed to load lights configuration");
+        }
+    };
+
+    // the standalone WebSocket listener only runs when a port is given
+    let ws_port: Option<u16> = match env::var("WS_PORT") {
+        Ok(val) => match val.parse() {
+            Ok(port) => Some(port),
+            Err(err) => abort(format!("Invalid WS_PORT {:?}: {}", val, err)),
+        },
+        Err(_err) => None,
+    };
+
+    if let Some(location) = &config.location {
+        if let Err(err) = location.validate() {
+            abort(format!("Invalid location: {}", err));
+        }
+    }
+
+    let zone_configs = match config.zones() {
+        Ok(zone_configs) => zone_configs,
+        Err(err) => abort(format!("Invalid zone configuration: {}", err)),
+    };
+
+    let mut presets = match Presets::load(config.data_dir.join("presets.json")) {
+        Ok(presets) => presets,
+        Err(err) => abort(format!("Failed to load presets: {}", err)),
+    };
+
+    let state_path = config.data_dir.join("state.json");
+
+    // a bad state file should not keep the lights from coming on
+    let saved: SavedState = match storage::load(&state_path) {
+        Ok(saved) => saved.unwrap_or_default(),
+        Err(err) => {
+            eprintln!("Ignoring saved light state: {}", err);
+            SavedState::new()
+        }
+    };
+
+    let mut lights = BTreeMap::new();
+    let mut simulated = SimulatedLogs::new();
+
+    for (name, zone_config) in zone_configs.iter() {
+        let (output, log) = open_output(name, zone_config);
+
+        if let Some(log) = log {
+            simulated.insert(name.clone(), log);
+        }
+
+        let (pattern, brightness) = match &config.power_on {
+            PowerOn::Restore => match saved.get(name) {
+                Some(state) => (state.pattern.clone(), state.brightness),
+                None => (Pattern::Solid(initial), u8::MAX),
+            },
+            PowerOn::Off => (Pattern::Off, u8::MAX),
+            PowerOn::Preset(preset) => match presets.get_unshared(preset) {
+                Some(pattern) => (pattern.clone(), u8::MAX),
+                None => {
+                    eprintln!("Power-on preset {} does not exist, turning off", preset);
+                    (Pattern::Off, u8::MAX)
+                }
+            },
+        };
+
+        lights.insert(
+            name.clone(),
+            Arc::new(Mutex::new(Lights::new(
+                name.clone(),
+                output,
+                pattern,
+                brightness,
+            ))),
+        );
+    }
+
+    let zones = Arc::new(Zones {
+        default: config.default_zone.clone(),
+        lights,
+    });
+
+    let presets = Arc::new(presets);
+
+    let schedules = match Schedules::load(config.data_dir.join("schedules.json")) {
+        Ok(schedules) => Arc::new(schedules),
+        Err(err) => abort(format!("Failed to load schedules: {}", err)),
+    };
+
+    let alarms = match Alarms::load(config.data_dir.join("alarms.json")) {
+        Ok(alarms) => Arc::new(alarms),
+        Err(err) => abort(format!("Failed to load alarms: {}", err)),
+    };
+
+    let zones_rocket = Arc::clone(&zones);
+    let zones_ws = Arc::clone(&zones);
+    let zones_osc = Arc::clone(&zones);
+    let zones_output = Arc::clone(&zones);
+    let zones_state = Arc::clone(&zones);
+    let zones_scheduler = Arc::clone(&zones);
+    let zones_alarm = Arc::clone(&zones);
+
+    let presets_rocket = Arc::clone(&presets);
+    let presets_ws = Arc::clone(&presets);
+    let presets_osc = Arc::clone(&presets);
+    let presets_scheduler = Arc::clone(&presets);
+
+    let schedules_rocket = Arc::clone(&schedules);
+
+    let alarms_rocket = Arc::clone(&alarms);
+    let alarms_osc = Arc::clone(&alarms);
+
+    let location = config.location;
+
+    rocket::custom(figment)
+        .mount(
+            "/",
+            routes![
+                get_zones,
+                ws,
+                zone_ws,
+                get_status,
+                get_zone_status,
+                get_color,
+                set_color,
+                get_pattern,
+                set_pattern,
+                get_zone_color,
+                set_zone_color,
+                get_zone_pattern,
+                set_zone_pattern,
+                get_brightness,
+                set_brightness,
+                get_zone_brightness,
+                set_zone_brightness,
+                get_simulated,
+                clear_simulated,
+                get_zone_simulated,
+                clear_zone_simulated,
+                get_presets,
+                get_preset,
+                set_preset,
+                delete_preset,
+                activate_preset,
+                get_schedules,
+                get_schedule,
+                set_schedule,
+                delete_schedule,
+                get_timer,
+                set_timer,
+                cancel_timer,
+                get_alarm,
+                set_alarm,
+                cancel_alarm,
+                ws_info,
+                files,
+                service_worker,
+                manifest,
+                form,
+                form_submit
+            ],
+        )
+        .register(
+            "/",
+            catchers![
+                bad_request,
+                unprocessable_entity,
+                not_found,
+                internal_server_error
+            ],
+        )
+        .manage(zones_rocket)
+        .manage(presets_rocket)
+        .manage(schedules_rocket)
+        .manage(alarms_rocket)
+        .manage(config.location)
+        .manage(simulated)
+        .attach(Template::fairing())
+        .attach(AdHoc::on_liftoff("WebSocket Server", move |_rocket| {
+            Box::pin(async move {
+                tokio::spawn(async move {
+                    if let Some(port) = ws_port {
+                        ws_server(zones_ws, presets_ws, port).await;
+                    }
+                });
+            })
+        }))
+        .attach(AdHoc::on_liftoff("OSC Server", move |_rocket| {
+            Box::pin(async move {
+                tokio::spawn(async move {
+                    osc_server(zones_osc, presets_osc, alarms_osc).await;
+                });
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Light Pattern Output", move |_rocket| {
+            Box::pin(async move {
+                for (name, lights) in zones_output.lights.iter() {
+                    let name = name.clone();
+                    let lights = Arc::clone(lights);
+
+                    tokio::spawn(async move {
+                        pattern_output(name, lights, chronon).await;
+                    });
+                }
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Light State Saver", move |_rocket| {
+            Box::pin(async move {
+                tokio::spawn(async move {
+                    state_saver(zones_state, state_path, save_period).await;
+                });
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Scheduler", move |_rocket| {
+            Box::pin(async move {
+                tokio::spawn(async move {
+                    scheduler(zones_scheduler, presets_scheduler, schedules, location).await;
+                });
+            })
+        }))
+        .attach(AdHoc::on_liftoff("Alarm Runner", move |_rocket| {
+            Box::pin(async move {
+                tokio::spawn(async move {
+                    alarm_runner(zones_alarm, alarms, alarm_period).await;
+                });
+            })
+        }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(red: u8) -> Frame {
+        Frame {
+            color: Color {
+                red,
+                green: 0,
+                blue: 0,
+                white: 0,
+            },
+            duration: Duration::from_millis(100),
+            transition: None,
+        }
+    }
+
+    fn custom(frames: usize, mode: Playback) -> CustomPattern {
+        CustomPattern {
+            frames: (0..frames).map(|red| frame(red as u8)).collect(),
+            mode,
+            then: None,
+        }
+    }
+
+    fn lights() -> Lights {
+        Lights::new(
+            String::from("test"),
+            Box::new(SimulatedOutput::new(&Default::default())),
+            Pattern::Off,
+            u8::MAX,
+        )
+    }
+
+    // frame indices shown in each successive 100ms
+    fn played(custom: &CustomPattern, count: usize) -> Vec<usize> {
+        let mut step = 0;
+        let mut cycles = 0;
+
+        (0..count)
+            .map(|_| {
+                let shown = custom.index(step);
+
+                custom.advance(&mut step, &mut cycles, Duration::from_millis(100));
+
+                shown
+            })
+            .collect()
+    }
+
+    #[test]
+    fn once_holds_last_frame() {
+        let custom = custom(2, Playback::Once);
+        let mut step = 0;
+        let mut cycles = 0;
+
+        let remaining = custom.advance(&mut step, &mut cycles, Duration::from_millis(250));
+
+        assert_eq!((step, cycles), (1, 1));
+        assert!(custom.mode.finished(cycles));
+        assert_eq!(remaining, Duration::from_millis(50));
+        assert_eq!(played(&custom, 4), [0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn repeat_plays_count_times() {
+        let custom = custom(2, Playback::Repeat(NonZeroU32::new(2).unwrap()));
+
+        assert_eq!(played(&custom, 6), [0, 1, 0, 1, 1, 1]);
+
+        let mut step = 0;
+        let mut cycles = 0;
+        let remaining = custom.advance(&mut step, &mut cycles, Duration::from_millis(450));
+
+        assert_eq!((step, cycles), (1, 2));
+        assert_eq!(remaining, Duration::from_millis(50));
+        assert!(custom.mode.finished(cycles));
+    }
+
+    #[test]
+    fn repeat_zero_is_rejected() {
+        assert!(serde_json::from_str::<Playback>(r#"{"repeat": 0}"#).is_err());
+        assert!(serde_json::from_str::<Playback>(r#"{"repeat": 1}"#).is_ok());
+    }
+
+    #[test]
+    fn ping_pong_order() {
+        assert_eq!(played(&custom(1, Playback::PingPong), 3), [0, 0, 0]);
+        assert_eq!(played(&custom(2, Playback::PingPong), 5), [0, 1, 0, 1, 0]);
+        assert_eq!(
+            played(&custom(3, Playback::PingPong), 9),
+            [0, 1, 2, 1, 0, 1, 2, 1, 0]
+        );
+    }
+
+    #[test]
+    fn switches_to_then_when_finished() {
+        let then = Color {
+            red: 1,
+            green: 2,
+            blue: 3,
+            white: 4,
+        };
+        let pattern = Pattern::Custom(CustomPattern {
+            then: Some(Box::new(Pattern::Solid(then))),
+            ..custom(2, Playback::Once)
+        });
+
+        let mut lights = lights();
+
+        lights.set_pattern_since(&pattern, Duration::from_millis(150));
+        lights.tick(Duration::from_millis(10));
+        assert!(matches!(lights.get_pattern(), Pattern::Custom(_)));
+
+        lights.set_pattern_since(&pattern, Duration::from_millis(250));
+        lights.tick(Duration::from_millis(10));
+        assert!(matches!(lights.get_pattern(), Pattern::Solid(color) if *color == then));
+        assert!(lights.get() == then);
+    }
+
+    #[test]
+    fn settled_patterns_stop_rendering() {
+        let chronon = Duration::from_millis(10);
+        let color = Color {
+            red: 255,
+            green: 0,
+            blue: 0,
+            white: 0,
+        };
+        let wake = Wake {
+            duration: Duration::from_secs(60),
+        };
+
+        let mut lights = lights();
+
+        for pattern in [
+            Pattern::Solid(color),
+            Pattern::Strobe(Strobe { color, rate: 0.0 }),
+            Pattern::Breathe(Breathe {
+                color,
+                period: Duration::ZERO,
+            }),
+            Pattern::Candle(Candle {
+                color,
+                intensity: 0.0,
+            }),
+        ] {
+            lights.set_pattern(&pattern, None);
+            assert!(lights.tick(chronon).is_none());
+        }
+
+        lights.set_pattern_since(&Pattern::Wake(wake.clone()), Duration::from_secs(30));
+        assert!(lights.tick(chronon).is_some());
+
+        lights.set_pattern_since(&Pattern::Wake(wake), Duration::from_secs(61));
+        assert!(lights.tick(chronon).is_none());
+    }
 }
@@ -0,0 +1,187 @@
+use rocket::serde::Deserialize;
+
+use super::{Levels, Output, OutputError};
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ChannelCalibration {
+    gain: Option<f64>,
+    gamma: Option<f64>,
+    table: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct CalibrationConfig {
+    #[serde(default = "default_gamma")]
+    gamma: f64,
+
+    #[serde(default)]
+    red: ChannelCalibration,
+    #[serde(default)]
+    green: ChannelCalibration,
+    #[serde(default)]
+    blue: ChannelCalibration,
+    #[serde(default)]
+    white: ChannelCalibration,
+}
+
+fn default_gamma() -> f64 {
+    1.0
+}
+
+// correction for one channel, evaluated in floating point so that the low end
+// of a gamma curve survives until the backend quantizes it
+enum Curve {
+    Power { gain: f64, gamma: f64 },
+    // linearly interpolated between the configured entries
+    Table(Vec<u8>),
+}
+
+impl Curve {
+    fn apply(&self, value: f64) -> f64 {
+        let value = value.clamp(0.0, 1.0);
+
+        match self {
+            Curve::Power { gain, gamma } => (gain * value.powf(*gamma)).min(1.0),
+            Curve::Table(table) => {
+                let position = value * 255.0;
+                let index = (position as usize).min(table.len() - 2);
+                let from = table[index] as f64;
+                let to = table[index + 1] as f64;
+
+                (from + (to - from) * (position - index as f64)) / 255.0
+            }
+        }
+    }
+}
+
+impl CalibrationConfig {
+    fn curve(&self, name: &str, channel: &ChannelCalibration) -> Result<Curve, OutputError> {
+        if let Some(table) = &channel.table {
+            if table.len() != 256 {
+                return Err(OutputError::InvalidConfig(format!(
+                    "{} calibration table must have 256 entries, got {}",
+                    name,
+                    table.len()
+                )));
+            }
+
+            return Ok(Curve::Table(table.clone()));
+        }
+
+        let gain = channel.gain.unwrap_or(1.0);
+        let gamma = channel.gamma.unwrap_or(self.gamma);
+
+        if !gain.is_finite() || gain < 0.0 {
+            return Err(OutputError::InvalidConfig(format!(
+                "{} calibration gain must be a non-negative number, got {}",
+                name, gain
+            )));
+        }
+
+        if !gamma.is_finite() || gamma <= 0.0 {
+            return Err(OutputError::InvalidConfig(format!(
+                "{} calibration gamma must be a positive number, got {}",
+                name, gamma
+            )));
+        }
+
+        Ok(Curve::Power { gain, gamma })
+    }
+}
+
+/// Output wrapper applying gamma correction and per-channel calibration
+/// before colors reach the backend
+pub struct CalibratedOutput {
+    inner: Box<dyn Output>,
+
+    red: Curve,
+    green: Curve,
+    blue: Curve,
+    white: Curve,
+
+    buffer: Vec<Levels>,
+}
+
+impl CalibratedOutput {
+    pub fn new(
+        inner: Box<dyn Output>,
+        config: &CalibrationConfig,
+    ) -> Result<CalibratedOutput, OutputError> {
+        Ok(CalibratedOutput {
+            red: config.curve("red", &config.red)?,
+            green: config.curve("green", &config.green)?,
+            blue: config.curve("blue", &config.blue)?,
+            white: config.curve("white", &config.white)?,
+
+            buffer: Vec::with_capacity(inner.pixels()),
+
+            inner,
+        })
+    }
+}
+
+impl Output for CalibratedOutput {
+    fn pixels(&self) -> usize {
+        self.inner.pixels()
+    }
+
+    fn set(&mut self, pixels: &[Levels]) -> Result<(), OutputError> {
+        self.buffer.clear();
+
+        self.buffer.extend(pixels.iter().map(|levels| Levels {
+            red: self.red.apply(levels.red),
+            green: self.green.apply(levels.green),
+            blue: self.blue.apply(levels.blue),
+            white: self.white.apply(levels.white),
+        }));
+
+        self.inner.set(&self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_keeps_low_values_apart() {
+        let curve = Curve::Power {
+            gain: 1.0,
+            gamma: 2.2,
+        };
+
+        let levels: Vec<f64> = (1..20)
+            .map(|value| curve.apply(value as f64 / 255.0))
+            .collect();
+
+        assert!(levels[0] > 0.0);
+        assert!(levels.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(curve.apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn gain_saturates_at_full() {
+        let curve = Curve::Power {
+            gain: 1.5,
+            gamma: 1.0,
+        };
+
+        assert_eq!(curve.apply(0.5), 0.75);
+        assert_eq!(curve.apply(1.0), 1.0);
+    }
+
+    #[test]
+    fn table_interpolates_between_entries() {
+        let mut table = vec![0u8; 256];
+        table[1] = 10;
+        table[255] = 255;
+
+        let curve = Curve::Table(table);
+
+        for (value, expected) in [(0.0, 0.0), (0.5, 5.0), (1.0, 10.0), (255.0, 255.0)] {
+            assert!((curve.apply(value / 255.0) - expected / 255.0).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+#[cfg(feature = "gpio")]
+use rppal::gpio::{Gpio, OutputPin};
+#[cfg(feature = "gpio")]
+use rppal::pwm::{Channel as PwmChannel, Polarity, Pwm};
+
+use rocket::serde::Deserialize;
+
+use super::{Levels, Output, OutputError};
+
+#[derive(Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum GpioPwmMode {
+    #[default]
+    Software,
+    Hardware,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "gpio"), allow(dead_code))]
+pub struct GpioChannelConfig {
+    pin: u8,
+    #[serde(default)]
+    invert: bool,
+    #[serde(default)]
+    pwm: GpioPwmMode,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct GpioConfig {
+    #[serde(default = "default_frequency")]
+    frequency: f64,
+    #[serde(default = "default_hardware_frequency")]
+    hardware_frequency: f64,
+
+    #[serde(default = "default_red")]
+    red: GpioChannelConfig,
+    #[serde(default = "default_green")]
+    green: GpioChannelConfig,
+    #[serde(default = "default_blue")]
+    blue: GpioChannelConfig,
+    #[serde(default = "default_white")]
+    white: GpioChannelConfig,
+}
+
+fn default_frequency() -> f64 {
+    60.0
+}
+
+fn default_hardware_frequency() -> f64 {
+    1000.0
+}
+
+fn default_channel(pin: u8) -> GpioChannelConfig {
+    GpioChannelConfig {
+        pin,
+        invert: false,
+        pwm: GpioPwmMode::Software,
+    }
+}
+
+fn default_red() -> GpioChannelConfig {
+    default_channel(17)
+}
+
+fn default_green() -> GpioChannelConfig {
+    default_channel(27)
+}
+
+fn default_blue() -> GpioChannelConfig {
+    default_channel(22)
+}
+
+fn default_white() -> GpioChannelConfig {
+    default_channel(18)
+}
+
+impl Default for GpioConfig {
+    fn default() -> Self {
+        GpioConfig {
+            frequency: default_frequency(),
+            hardware_frequency: default_hardware_frequency(),
+
+            red: default_red(),
+            green: default_green(),
+            blue: default_blue(),
+            white: default_white(),
+        }
+    }
+}
+
+fn pwm_channel(pin: u8) -> Option<u8> {
+    match pin {
+        12 | 18 => Some(0),
+        13 | 19 => Some(1),
+        _ => None,
+    }
+}
+
+impl GpioConfig {
+    fn channels(&self) -> [(&'static str, &GpioChannelConfig); 4] {
+        [
+            ("red", &self.red),
+            ("green", &self.green),
+            ("blue", &self.blue),
+            ("white", &self.white),
+        ]
+    }
+
+    pub fn validate(&self) -> Result<(), OutputError> {
+        for (name, frequency) in [
+            ("PWM frequency", self.frequency),
+            ("hardware PWM frequency", self.hardware_frequency),
+        ] {
+            if !frequency.is_finite() || frequency <= 0.0 {
+                return Err(OutputError::InvalidConfig(format!(
+                    "{} must be a positive number of hertz, got {}",
+                    name, frequency
+                )));
+            }
+        }
+
+        let channels = self.channels();
+
+        for (index, (name, channel)) in channels.iter().enumerate() {
+            // BCM numbering only goes up to GPIO 27 on the 40-pin header
+            if channel.pin > 27 {
+                return Err(OutputError::InvalidConfig(format!(
+                    "{} channel is mapped to GPIO {}, which is not on the header (0-27)",
+                    name, channel.pin
+                )));
+            }
+
+            if channel.pwm == GpioPwmMode::Hardware && pwm_channel(channel.pin).is_none() {
+                return Err(OutputError::InvalidConfig(format!(
+                    "{} channel uses hardware PWM but GPIO {} is not PWM-capable (use 12, 13, 18 or 19)",
+                    name, channel.pin
+                )));
+            }
+
+            for (other, other_channel) in &channels[..index] {
+                if other_channel.pin == channel.pin {
+                    return Err(OutputError::InvalidConfig(format!(
+                        "{} and {} channels are both mapped to GPIO {}",
+                        other, name, channel.pin
+                    )));
+                }
+
+                if channel.pwm == GpioPwmMode::Hardware
+                    && other_channel.pwm == GpioPwmMode::Hardware
+                    && pwm_channel(channel.pin) == pwm_channel(other_channel.pin)
+                {
+                    return Err(OutputError::InvalidConfig(format!(
+                        "{} and {} channels both use hardware PWM on GPIO {} and {}, which share a PWM channel",
+                        other, name, other_channel.pin, channel.pin
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gpio")]
+enum GpioDriver {
+    Software(OutputPin),
+    Hardware(Pwm),
+}
+
+#[cfg(feature = "gpio")]
+struct GpioChannel {
+    driver: GpioDriver,
+    invert: bool,
+}
+
+#[cfg(feature = "gpio")]
+impl GpioChannel {
+    fn set(&mut self, frequency: f64, duty_cycle: f64) -> Result<(), OutputError> {
+        let duty_cycle = duty_cycle.clamp(0.0, 1.0);
+        let duty_cycle = if self.invert {
+            1.0 - duty_cycle
+        } else {
+            duty_cycle
+        };
+
+        match &mut self.driver {
+            GpioDriver::Software(pin) => pin.set_pwm_frequency(frequency, duty_cycle)?,
+            GpioDriver::Hardware(pwm) => pwm.set_duty_cycle(duty_cycle)?,
+        }
+
+        Ok(())
+    }
+}
+
+/// PWM output on four GPIO pins, using the hardware PWM peripheral where
+/// configured and software PWM otherwise
+#[cfg(feature = "gpio")]
+pub struct GpioOutput {
+    frequency: f64,
+
+    red: GpioChannel,
+    green: GpioChannel,
+    blue: GpioChannel,
+    white: GpioChannel,
+}
+
+#[cfg(feature = "gpio")]
+impl GpioOutput {
+    pub fn new(config: &GpioConfig) -> Result<GpioOutput, OutputError> {
+        config.validate()?;
+
+        let gpio = Gpio::new()?;
+
+        let channel = |name: &str, channel: &GpioChannelConfig| {
+            let driver = match channel.pwm {
+                GpioPwmMode::Software => gpio
+                    .get(channel.pin)
+                    .map(|pin| GpioDriver::Software(pin.into_output()))
+                    .map_err(|err| err.to_string()),
+                // hardware PWM pins are muxed by the PWM overlay, so they are
+                // deliberately not claimed through the GPIO interface
+                GpioPwmMode::Hardware => Pwm::with_frequency(
+                    match pwm_channel(channel.pin) {
+                        Some(0) => PwmChannel::Pwm0,
+                        _ => PwmChannel::Pwm1,
+                    },
+                    config.hardware_frequency,
+                    0.0,
+                    Polarity::Normal,
+                    true,
+                )
+                .map(GpioDriver::Hardware)
+                .map_err(|err| err.to_string()),
+            };
+
+            match driver {
+                Ok(driver) => Ok(GpioChannel {
+                    driver,
+                    invert: channel.invert,
+                }),
+                Err(err) => Err(OutputError::InvalidConfig(format!(
+                    "{} channel could not use GPIO {}: {}",
+                    name, channel.pin, err
+                ))),
+            }
+        };
+
+        Ok(GpioOutput {
+            frequency: config.frequency,
+
+            red: channel("red", &config.red)?,
+            green: channel("green", &config.green)?,
+            blue: channel("blue", &config.blue)?,
+            white: channel("white", &config.white)?,
+        })
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl Output for GpioOutput {
+    fn set(&mut self, pixels: &[Levels]) -> Result<(), OutputError> {
+        let levels = pixels[0];
+
+        self.red.set(self.frequency, levels.red)?;
+        self.green.set(self.frequency, levels.green)?;
+        self.blue.set(self.frequency, levels.blue)?;
+        self.white.set(self.frequency, levels.white)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "gpio"))]
+pub enum GpioOutput {}
+
+#[cfg(not(feature = "gpio"))]
+impl GpioOutput {
+    pub fn new(config: &GpioConfig) -> Result<GpioOutput, OutputError> {
+        config.validate()?;
+
+        Err(OutputError::Unavailable("gpio"))
+    }
+}
+
+#[cfg(not(feature = "gpio"))]
+impl Output for GpioOutput {
+    fn set(&mut self, _pixels: &[Levels]) -> Result<(), OutputError> {
+        match *self {}
+    }
+}
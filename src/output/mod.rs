@@ -0,0 +1,161 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use rocket::serde::Deserialize;
+
+use crate::Color;
+
+mod calibration;
+mod gpio;
+mod simulated;
+mod ws2812;
+
+pub use calibration::{CalibratedOutput, CalibrationConfig};
+pub use gpio::{GpioConfig, GpioOutput};
+pub use simulated::{SimulatedConfig, SimulatedLog, SimulatedOutput, SimulatedWrite};
+pub use ws2812::{Ws2812Config, Ws2812Output};
+
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase", tag = "type")]
+pub enum OutputConfig {
+    Gpio(GpioConfig),
+    Simulated(SimulatedConfig),
+    Ws2812(Ws2812Config),
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        if cfg!(feature = "gpio") {
+            OutputConfig::Gpio(GpioConfig::default())
+        } else {
+            OutputConfig::Simulated(SimulatedConfig::default())
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum OutputError {
+    InvalidConfig(String),
+    #[cfg(feature = "gpio")]
+    Gpio(rppal::gpio::Error),
+    #[cfg(feature = "gpio")]
+    Pwm(rppal::pwm::Error),
+    #[cfg(feature = "gpio")]
+    Spi(rppal::spi::Error),
+    #[cfg(not(feature = "gpio"))]
+    Unavailable(&'static str),
+}
+
+impl Error for OutputError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            OutputError::InvalidConfig(_) => None,
+            #[cfg(feature = "gpio")]
+            OutputError::Gpio(err) => Some(err),
+            #[cfg(feature = "gpio")]
+            OutputError::Pwm(err) => Some(err),
+            #[cfg(feature = "gpio")]
+            OutputError::Spi(err) => Some(err),
+            #[cfg(not(feature = "gpio"))]
+            OutputError::Unavailable(_) => None,
+        }
+    }
+}
+
+impl Display for OutputError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            OutputError::InvalidConfig(message) => {
+                write!(f, "invalid configuration: {}", message)
+            }
+            #[cfg(feature = "gpio")]
+            OutputError::Gpio(err) => {
+                write!(f, "GPIO error: {}", err)
+            }
+            #[cfg(feature = "gpio")]
+            OutputError::Pwm(err) => {
+                write!(f, "PWM error: {}", err)
+            }
+            #[cfg(feature = "gpio")]
+            OutputError::Spi(err) => {
+                write!(f, "SPI error: {}", err)
+            }
+            #[cfg(not(feature = "gpio"))]
+            OutputError::Unavailable(feature) => {
+                write!(
+                    f,
+                    "this build does not include the `{}` feature; rebuild with `--features {}` or configure a different output",
+                    feature, feature
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl From<rppal::gpio::Error> for OutputError {
+    fn from(err: rppal::gpio::Error) -> Self {
+        OutputError::Gpio(err)
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl From<rppal::pwm::Error> for OutputError {
+    fn from(err: rppal::pwm::Error) -> Self {
+        OutputError::Pwm(err)
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl From<rppal::spi::Error> for OutputError {
+    fn from(err: rppal::spi::Error) -> Self {
+        OutputError::Spi(err)
+    }
+}
+
+/// Channel levels from 0 to 1 on their way to the output, kept in floating
+/// point so that brightness and calibration do not round away dim values
+/// before the backend can show them
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Levels {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    pub white: f64,
+}
+
+impl Levels {
+    pub fn new(color: Color, brightness: u8) -> Levels {
+        let scale = |value: u8| value as f64 / 255.0 * (brightness as f64 / 255.0);
+
+        Levels {
+            red: scale(color.red),
+            green: scale(color.green),
+            blue: scale(color.blue),
+            white: scale(color.white),
+        }
+    }
+
+    /// Round to 8 bits per channel for backends that cannot do better
+    pub fn to_color(self) -> Color {
+        let round = |value: f64| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        Color {
+            red: round(self.red),
+            green: round(self.green),
+            blue: round(self.blue),
+            white: round(self.white),
+        }
+    }
+}
+
+/// A sink for the colors rendered by `Lights`
+pub trait Output: Send {
+    /// Number of individually addressable pixels
+    fn pixels(&self) -> usize {
+        1
+    }
+
+    /// Write one color per pixel; `pixels` always has `self.pixels()` entries
+    fn set(&mut self, pixels: &[Levels]) -> Result<(), OutputError>;
+}
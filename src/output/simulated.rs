@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::time::{Duration, Instant};
+
+use serde_with::{serde_as, DurationMilliSeconds};
+
+use crate::Color;
+
+use super::{Levels, Output, OutputError};
+
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SimulatedConfig {
+    #[serde(default = "default_pixels")]
+    pixels: usize,
+    #[serde(default = "default_history")]
+    history: usize,
+    #[serde(default = "default_log")]
+    log: bool,
+}
+
+fn default_pixels() -> usize {
+    1
+}
+
+fn default_history() -> usize {
+    1024
+}
+
+fn default_log() -> bool {
+    true
+}
+
+impl Default for SimulatedConfig {
+    fn default() -> Self {
+        SimulatedConfig {
+            pixels: default_pixels(),
+            history: default_history(),
+            log: default_log(),
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Serialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SimulatedWrite {
+    #[serde_as(as = "DurationMilliSeconds")]
+    elapsed: Duration,
+    pixels: Vec<Color>,
+}
+
+pub type SimulatedLog = Arc<Mutex<VecDeque<SimulatedWrite>>>;
+
+/// Output that records colors instead of driving any hardware
+pub struct SimulatedOutput {
+    config: SimulatedConfig,
+
+    started: Instant,
+    log: SimulatedLog,
+}
+
+impl SimulatedOutput {
+    pub fn new(config: &SimulatedConfig) -> SimulatedOutput {
+        SimulatedOutput {
+            config: config.clone(),
+
+            started: Instant::now(),
+            log: Arc::new(Mutex::new(VecDeque::with_capacity(config.history))),
+        }
+    }
+
+    pub fn log(&self) -> SimulatedLog {
+        Arc::clone(&self.log)
+    }
+}
+
+impl Output for SimulatedOutput {
+    fn pixels(&self) -> usize {
+        self.config.pixels
+    }
+
+    fn set(&mut self, pixels: &[Levels]) -> Result<(), OutputError> {
+        let elapsed = self.started.elapsed();
+        let pixels: Vec<Color> = pixels.iter().map(|levels| levels.to_color()).collect();
+
+        if self.config.log {
+            println!(
+                "Simulated output set to {} at {:?}",
+                pixels
+                    .iter()
+                    .map(|color| color.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" "),
+                elapsed
+            );
+        }
+
+        let mut log = self.log.lock().unwrap();
+
+        while !log.is_empty() && log.len() >= self.config.history {
+            log.pop_front();
+        }
+
+        if self.config.history > 0 {
+            log.push_back(SimulatedWrite { elapsed, pixels });
+        }
+
+        Ok(())
+    }
+}
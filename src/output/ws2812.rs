@@ -0,0 +1,323 @@
+#[cfg(feature = "gpio")]
+use std::fs;
+
+#[cfg(feature = "gpio")]
+use rppal::spi::{Bus, Mode, SlaveSelect, Spi};
+
+use rocket::serde::Deserialize;
+
+#[cfg(any(feature = "gpio", test))]
+use crate::Color;
+
+use super::{Levels, Output, OutputError};
+
+/// SPI clock rate at which three SPI bits make up one LED bit (~417 ns each)
+#[cfg(feature = "gpio")]
+const SPI_CLOCK: u32 = 2_400_000;
+
+/// Trailing low time latching the data into the strip (300 µs at `SPI_CLOCK`)
+#[cfg(any(feature = "gpio", test))]
+const RESET_BYTES: usize = 90;
+
+/// Largest single spidev transfer unless raised with `spidev.bufsiz`
+#[cfg(any(feature = "gpio", test))]
+const DEFAULT_BUFSIZ: usize = 4096;
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum ColorOrder {
+    Rgb,
+    #[default]
+    Grb,
+    Rgbw,
+    Grbw,
+}
+
+#[cfg(any(feature = "gpio", test))]
+impl ColorOrder {
+    fn width(self) -> usize {
+        match self {
+            ColorOrder::Rgb | ColorOrder::Grb => 3,
+            ColorOrder::Rgbw | ColorOrder::Grbw => 4,
+        }
+    }
+
+    fn components(self, color: &Color) -> ([u8; 4], usize) {
+        match self {
+            ColorOrder::Rgb => ([color.red, color.green, color.blue, 0], 3),
+            ColorOrder::Grb => ([color.green, color.red, color.blue, 0], 3),
+            ColorOrder::Rgbw => ([color.red, color.green, color.blue, color.white], 4),
+            ColorOrder::Grbw => ([color.green, color.red, color.blue, color.white], 4),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[cfg_attr(not(feature = "gpio"), allow(dead_code))]
+pub struct Ws2812Config {
+    pixels: usize,
+    #[serde(default)]
+    order: ColorOrder,
+    #[serde(default)]
+    bus: u8,
+    #[serde(default)]
+    slave_select: u8,
+}
+
+impl Ws2812Config {
+    pub fn validate(&self) -> Result<(), OutputError> {
+        if self.pixels == 0 {
+            return Err(OutputError::InvalidConfig(String::from(
+                "addressable strip must have at least one pixel",
+            )));
+        }
+
+        if self.bus > 6 {
+            return Err(OutputError::InvalidConfig(format!(
+                "SPI bus {} does not exist (0-6)",
+                self.bus
+            )));
+        }
+
+        if self.slave_select > 2 {
+            return Err(OutputError::InvalidConfig(format!(
+                "SPI slave select {} does not exist (0-2)",
+                self.slave_select
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Length of the SPI transfer for one frame, which must be written in one
+    /// go since a gap between transfers would latch a partial frame
+    #[cfg(any(feature = "gpio", test))]
+    fn transfer_len(&self) -> usize {
+        self.pixels * self.order.width() * 3 + RESET_BYTES
+    }
+}
+
+#[cfg(feature = "gpio")]
+fn spidev_bufsiz() -> usize {
+    fs::read_to_string("/sys/module/spidev/parameters/bufsiz")
+        .ok()
+        .and_then(|bufsiz| bufsiz.trim().parse().ok())
+        .unwrap_or(DEFAULT_BUFSIZ)
+}
+
+#[cfg(any(feature = "gpio", test))]
+fn encode_byte(buffer: &mut Vec<u8>, value: u8) {
+    let mut bits: u32 = 0;
+
+    // each LED bit is sent MSB first as 0b110 (one) or 0b100 (zero)
+    for bit in (0..8).rev() {
+        let symbol = if value & (1 << bit) != 0 {
+            0b110
+        } else {
+            0b100
+        };
+
+        bits = (bits << 3) | symbol;
+    }
+
+    buffer.extend_from_slice(&[(bits >> 16) as u8, (bits >> 8) as u8, bits as u8]);
+}
+
+/// Encode pixels as the SPI bitstream for a WS2812/SK6812 strip
+#[cfg(any(feature = "gpio", test))]
+pub fn encode(pixels: &[Color], order: ColorOrder) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(pixels.len() * 4 * 3 + RESET_BYTES);
+
+    for pixel in pixels {
+        let (components, count) = order.components(pixel);
+
+        for value in &components[..count] {
+            encode_byte(&mut buffer, *value);
+        }
+    }
+
+    buffer.resize(buffer.len() + RESET_BYTES, 0);
+
+    buffer
+}
+
+/// Addressable WS2812/SK6812 strip driven from the SPI MOSI pin
+#[cfg(feature = "gpio")]
+pub struct Ws2812Output {
+    pixels: usize,
+    order: ColorOrder,
+
+    spi: Spi,
+}
+
+#[cfg(feature = "gpio")]
+impl Ws2812Output {
+    pub fn new(config: &Ws2812Config) -> Result<Ws2812Output, OutputError> {
+        config.validate()?;
+
+        let bufsiz = spidev_bufsiz();
+
+        if config.transfer_len() > bufsiz {
+            return Err(OutputError::InvalidConfig(format!(
+                "{} pixels need a {} byte SPI transfer but spidev is limited to {} bytes, raise it with spidev.bufsiz={} on the kernel command line",
+                config.pixels,
+                config.transfer_len(),
+                bufsiz,
+                config.transfer_len()
+            )));
+        }
+
+        let bus = match config.bus {
+            0 => Bus::Spi0,
+            1 => Bus::Spi1,
+            2 => Bus::Spi2,
+            3 => Bus::Spi3,
+            4 => Bus::Spi4,
+            5 => Bus::Spi5,
+            _ => Bus::Spi6,
+        };
+
+        let slave_select = match config.slave_select {
+            0 => SlaveSelect::Ss0,
+            1 => SlaveSelect::Ss1,
+            _ => SlaveSelect::Ss2,
+        };
+
+        Ok(Ws2812Output {
+            pixels: config.pixels,
+            order: config.order,
+
+            spi: Spi::new(bus, slave_select, SPI_CLOCK, Mode::Mode0)?,
+        })
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl Output for Ws2812Output {
+    fn pixels(&self) -> usize {
+        self.pixels
+    }
+
+    fn set(&mut self, pixels: &[Levels]) -> Result<(), OutputError> {
+        let pixels: Vec<Color> = pixels.iter().map(|levels| levels.to_color()).collect();
+
+        self.spi.write(&encode(&pixels, self.order))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "gpio"))]
+pub enum Ws2812Output {}
+
+#[cfg(not(feature = "gpio"))]
+impl Ws2812Output {
+    pub fn new(config: &Ws2812Config) -> Result<Ws2812Output, OutputError> {
+        config.validate()?;
+
+        Err(OutputError::Unavailable("gpio"))
+    }
+}
+
+#[cfg(not(feature = "gpio"))]
+impl Output for Ws2812Output {
+    fn set(&mut self, _pixels: &[Levels]) -> Result<(), OutputError> {
+        match *self {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZERO: [u8; 3] = [0x92, 0x49, 0x24];
+    const FULL: [u8; 3] = [0xdb, 0x6d, 0xb6];
+
+    fn red() -> Color {
+        Color {
+            red: 0xff,
+            green: 0,
+            blue: 0,
+            white: 0,
+        }
+    }
+
+    #[test]
+    fn encodes_grb_pixel() {
+        let mut expected = Vec::new();
+        expected.extend_from_slice(&ZERO);
+        expected.extend_from_slice(&FULL);
+        expected.extend_from_slice(&ZERO);
+        expected.extend_from_slice(&[0; RESET_BYTES]);
+
+        assert_eq!(encode(&[red()], ColorOrder::Grb), expected);
+    }
+
+    #[test]
+    fn encodes_mixed_bits_msb_first() {
+        let mut buffer = Vec::new();
+        encode_byte(&mut buffer, 0b1010_0001);
+
+        // 110 100 110 100 100 100 100 110
+        assert_eq!(buffer, [0xd3, 0x49, 0x26]);
+    }
+
+    #[test]
+    fn encodes_rgbw_with_four_components() {
+        let color = Color {
+            red: 0xff,
+            green: 0,
+            blue: 0,
+            white: 0xff,
+        };
+
+        for (order, components) in [
+            (ColorOrder::Rgbw, [FULL, ZERO, ZERO, FULL]),
+            (ColorOrder::Grbw, [ZERO, FULL, ZERO, FULL]),
+        ] {
+            let buffer = encode(&[color, color], order);
+
+            assert_eq!(buffer.len(), 2 * 4 * 3 + RESET_BYTES);
+
+            let expected = components.concat();
+            assert_eq!(&buffer[..12], &expected[..]);
+            assert_eq!(&buffer[12..24], &expected[..]);
+        }
+    }
+
+    #[test]
+    fn encodes_rgb_without_white() {
+        let buffer = encode(&[red()], ColorOrder::Rgb);
+
+        assert_eq!(buffer.len(), 3 * 3 + RESET_BYTES);
+        assert_eq!(&buffer[..9], &[FULL, ZERO, ZERO].concat()[..]);
+    }
+
+    #[test]
+    fn transfer_len_matches_encoding() {
+        for order in [ColorOrder::Rgb, ColorOrder::Grbw] {
+            let config = Ws2812Config {
+                pixels: 3,
+                order,
+                bus: 0,
+                slave_select: 0,
+            };
+
+            assert_eq!(config.transfer_len(), encode(&[red(); 3], order).len());
+        }
+    }
+
+    #[test]
+    fn default_bufsiz_fits_445_rgb_pixels() {
+        let config = |pixels| Ws2812Config {
+            pixels,
+            order: ColorOrder::Rgb,
+            bus: 0,
+            slave_select: 0,
+        };
+
+        assert!(config(445).transfer_len() <= DEFAULT_BUFSIZ);
+        assert!(config(446).transfer_len() > DEFAULT_BUFSIZ);
+    }
+}
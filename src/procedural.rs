@@ -0,0 +1,319 @@
+use std::f64::consts::TAU;
+
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::time::Duration;
+
+use serde_with::{serde_as, DurationMilliSeconds};
+
+use crate::Color;
+
+const BLACK: Color = Color {
+    red: 0,
+    green: 0,
+    blue: 0,
+    white: 0,
+};
+
+fn default_candle_color() -> Color {
+    Color {
+        red: 255,
+        green: 147,
+        blue: 41,
+        white: 0,
+    }
+}
+
+fn default_rainbow_period() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_breathe_period() -> Duration {
+    Duration::from_secs(4)
+}
+
+fn default_strobe_rate() -> f64 {
+    10.0
+}
+
+fn default_candle_intensity() -> f64 {
+    0.5
+}
+
+pub fn default_wake_duration() -> Duration {
+    Duration::from_secs(30 * 60)
+}
+
+// fraction of the way through the current cycle, or the start of the cycle
+// for a cycle length of zero
+fn phase(elapsed: Duration, period: Duration) -> f64 {
+    if period.is_zero() {
+        0.0
+    } else {
+        (elapsed.as_secs_f64() / period.as_secs_f64()).fract()
+    }
+}
+
+// xorshift scrambled lattice value in [0, 1)
+fn lattice(n: u64) -> f64 {
+    let mut x = n.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ 0x2545_f491_4f6c_dd1d;
+
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+
+    (x.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Smooth noise in [0, 1) that varies about once per unit of `t`, so that
+/// flickering patterns stay a pure function of the elapsed time
+fn noise(t: f64, seed: u64) -> f64 {
+    let index = t.floor();
+    let fraction = t - index;
+    let index = (index as u64).wrapping_add(seed << 32);
+
+    let from = lattice(index);
+    let to = lattice(index.wrapping_add(1));
+
+    from + (to - from) * fraction * fraction * (3.0 - 2.0 * fraction)
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Rainbow {
+    #[serde_as(as = "DurationMilliSeconds")]
+    #[serde(default = "default_rainbow_period")]
+    pub period: Duration,
+    /// How many times the hues repeat along the strip, where 0 shows the
+    /// same hue on every pixel
+    #[serde(default)]
+    pub spread: f64,
+}
+
+impl Rainbow {
+    pub fn animating(&self) -> bool {
+        !self.period.is_zero()
+    }
+
+    /// Color at `position` from 0 (first pixel) to 1 (past the last pixel)
+    pub fn color(&self, elapsed: Duration, position: f64) -> Color {
+        let spread = if self.spread.is_finite() {
+            self.spread
+        } else {
+            0.0
+        };
+        let hue = (phase(elapsed, self.period) + position * spread).rem_euclid(1.0) * 6.0;
+        let rising = ((hue % 1.0) * 255.0).round() as u8;
+        let falling = 255 - rising;
+
+        let (red, green, blue) = match hue as u8 {
+            0 => (255, rising, 0),
+            1 => (falling, 255, 0),
+            2 => (0, 255, rising),
+            3 => (0, falling, 255),
+            4 => (rising, 0, 255),
+            _ => (255, 0, falling),
+        };
+
+        Color {
+            red,
+            green,
+            blue,
+            white: 0,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Breathe {
+    pub color: Color,
+    #[serde_as(as = "DurationMilliSeconds")]
+    #[serde(default = "default_breathe_period")]
+    pub period: Duration,
+}
+
+impl Breathe {
+    pub fn animating(&self) -> bool {
+        !self.period.is_zero()
+    }
+
+    pub fn color(&self, elapsed: Duration) -> Color {
+        let level = (1.0 - (phase(elapsed, self.period) * TAU).cos()) / 2.0;
+
+        BLACK.mix(self.color, level)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Strobe {
+    pub color: Color,
+    /// Flashes per second
+    #[serde(default = "default_strobe_rate")]
+    pub rate: f64,
+}
+
+impl Strobe {
+    pub fn animating(&self) -> bool {
+        self.rate.is_finite() && self.rate > 0.0
+    }
+
+    pub fn color(&self, elapsed: Duration) -> Color {
+        if !self.animating() {
+            return self.color;
+        }
+
+        if (elapsed.as_secs_f64() * self.rate).fract() < 0.5 {
+            self.color
+        } else {
+            BLACK
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Candle {
+    #[serde(default = "default_candle_color")]
+    pub color: Color,
+    /// How far the flame dims when flickering, from 0 (steady) to 1
+    #[serde(default = "default_candle_intensity")]
+    pub intensity: f64,
+}
+
+impl Candle {
+    fn intensity(&self) -> f64 {
+        if self.intensity.is_finite() {
+            self.intensity.clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    pub fn animating(&self) -> bool {
+        self.intensity() > 0.0
+    }
+
+    pub fn color(&self, elapsed: Duration) -> Color {
+        let t = elapsed.as_secs_f64();
+
+        // a slow sway with faster flicker on top
+        let flicker = noise(t * 2.0, 1) * 0.6 + noise(t * 9.0, 2) * 0.4;
+        let intensity = self.intensity();
+
+        BLACK.mix(self.color, 1.0 - intensity * flicker)
+    }
+}
+
+pub fn fire(elapsed: Duration) -> Color {
+    let t = elapsed.as_secs_f64();
+
+    let heat = noise(t * 3.0, 3) * 0.7 + noise(t * 11.0, 4) * 0.3;
+
+    let ember = Color {
+        red: 180,
+        green: 20,
+        blue: 0,
+        white: 0,
+    };
+    let flame = Color {
+        red: 255,
+        green: 170,
+        blue: 20,
+        white: 0,
+    };
+
+    BLACK.mix(ember.mix(flame, heat), 0.6 + heat * 0.4)
+}
+
+/// Sunrise simulation that brightens from off through deep red, orange and
+/// warm white to full brightness over `duration`, then holds
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Wake {
+    #[serde_as(as = "DurationMilliSeconds")]
+    #[serde(default = "default_wake_duration")]
+    pub duration: Duration,
+}
+
+// colors along the ramp and how far through it they are reached
+const WAKE_STOPS: [(f64, Color); 6] = [
+    (0.0, BLACK),
+    (
+        0.2,
+        Color {
+            red: 60,
+            green: 0,
+            blue: 0,
+            white: 0,
+        },
+    ),
+    (
+        0.45,
+        Color {
+            red: 200,
+            green: 30,
+            blue: 0,
+            white: 0,
+        },
+    ),
+    (
+        0.65,
+        Color {
+            red: 255,
+            green: 110,
+            blue: 10,
+            white: 20,
+        },
+    ),
+    (
+        0.85,
+        Color {
+            red: 255,
+            green: 170,
+            blue: 60,
+            white: 120,
+        },
+    ),
+    (
+        1.0,
+        Color {
+            red: 255,
+            green: 200,
+            blue: 120,
+            white: 255,
+        },
+    ),
+];
+
+impl Wake {
+    /// The ramp holds its final color once `duration` has passed
+    pub fn animating(&self, elapsed: Duration) -> bool {
+        elapsed < self.duration
+    }
+
+    pub fn color(&self, elapsed: Duration) -> Color {
+        let progress = if self.duration.is_zero() {
+            1.0
+        } else {
+            (elapsed.as_secs_f64() / self.duration.as_secs_f64()).min(1.0)
+        };
+
+        for window in WAKE_STOPS.windows(2) {
+            let (from_progress, from) = window[0];
+            let (to_progress, to) = window[1];
+
+            if progress <= to_progress {
+                return from.mix(
+                    to,
+                    (progress - from_progress) / (to_progress - from_progress),
+                );
+            }
+        }
+
+        WAKE_STOPS[WAKE_STOPS.len() - 1].1
+    }
+}
@@ -0,0 +1,596 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, Local, NaiveDateTime, NaiveTime, TimeZone,
+    Timelike, Utc,
+};
+
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::time;
+use rocket::tokio::time::Duration;
+
+use serde_with::{serde_as, DurationMilliSeconds};
+
+use yansi::Paint;
+
+use crate::config::Location;
+use crate::storage::Collection;
+use crate::sun::{self, SunEvent};
+use crate::{Pattern, SharedPresets, SharedZones, Zones};
+
+// allowed values of each cron field, as in crontab(5)
+const CRON_FIELDS: [(&str, u32, u32); 5] = [
+    ("minute", 0, 59),
+    ("hour", 0, 23),
+    ("day of month", 1, 31),
+    ("month", 1, 12),
+    ("day of week", 0, 7),
+];
+
+/// A five-field cron expression (minute, hour, day of month, month, day of
+/// week) supporting `*`, lists, ranges and steps
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", try_from = "String", into = "String")]
+pub struct Cron {
+    source: String,
+    // one bit per allowed value
+    fields: [u64; 5],
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+fn parse_cron_field(field: &str, name: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut bits = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step \"{}\" in {} field", step, name)),
+            },
+            None => (part, 1),
+        };
+
+        let value = |value: &str| match value.parse::<u32>() {
+            Ok(value) if (min..=max).contains(&value) => Ok(value),
+            _ => Err(format!(
+                "invalid value \"{}\" in {} field (expected {}-{})",
+                value, name, min, max
+            )),
+        };
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            range => match range.split_once('-') {
+                Some((start, end)) => (value(start)?, value(end)?),
+                // a single value with a step runs to the end of the field
+                None if part.contains('/') => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+
+        if start > end {
+            return Err(format!("invalid range \"{}\" in {} field", range, name));
+        }
+
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Cron, String> {
+        let parts: Vec<&str> = source.split_whitespace().collect();
+
+        if parts.len() != CRON_FIELDS.len() {
+            return Err(format!(
+                "cron expression \"{}\" must have 5 fields (minute, hour, day of month, month, day of week)",
+                source
+            ));
+        }
+
+        let mut fields = [0; 5];
+
+        for (index, (name, min, max)) in CRON_FIELDS.iter().enumerate() {
+            fields[index] = parse_cron_field(parts[index], name, *min, *max)?;
+        }
+
+        // both 0 and 7 are Sunday
+        if fields[4] & (1 << 7) != 0 {
+            fields[4] |= 1;
+        }
+
+        Ok(Cron {
+            source: source.to_string(),
+            fields,
+            // as in cron, a field starting with `*` (such as `*/2`) does not
+            // restrict the day
+            any_day_of_month: parts[2].starts_with('*'),
+            any_day_of_week: parts[4].starts_with('*'),
+        })
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<Cron, String> {
+        source.parse()
+    }
+}
+
+impl From<Cron> for String {
+    fn from(cron: Cron) -> String {
+        cron.source
+    }
+}
+
+impl Cron {
+    fn matches(&self, time: &NaiveDateTime) -> bool {
+        let bit = |field: usize, value: u32| self.fields[field] & (1 << value) != 0;
+
+        let day_of_month = bit(2, time.day());
+        let day_of_week = bit(4, time.weekday().num_days_from_sunday());
+
+        // like cron, a restricted day of month and day of week match either
+        let day = match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        };
+
+        bit(0, time.minute()) && bit(1, time.hour()) && bit(3, time.month()) && day
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum Day {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+fn every_day() -> Vec<Day> {
+    vec![
+        Day::Mon,
+        Day::Tue,
+        Day::Wed,
+        Day::Thu,
+        Day::Fri,
+        Day::Sat,
+        Day::Sun,
+    ]
+}
+
+/// A local time of day written as `HH:MM`
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    hour: u32,
+    minute: u32,
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(source: String) -> Result<TimeOfDay, String> {
+        let parsed = source
+            .split_once(':')
+            .and_then(|(hour, minute)| Some((hour.parse().ok()?, minute.parse().ok()?)));
+
+        match parsed {
+            Some((hour, minute)) if hour < 24 && minute < 60 => Ok(TimeOfDay { hour, minute }),
+            _ => Err(format!("invalid time \"{}\" (expected HH:MM)", source)),
+        }
+    }
+}
+
+impl TimeOfDay {
+    /// The first time after `now` that is this time of day, skipping days
+    /// where it does not exist because of a daylight saving time change
+    pub fn next_after(&self, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let time = NaiveTime::from_hms_opt(self.hour, self.minute, 0)?;
+
+        (0..=2)
+            .filter_map(|days| {
+                let date = now.date_naive() + ChronoDuration::days(days);
+
+                Local.from_local_datetime(&date.and_time(time)).earliest()
+            })
+            .find(|at| *at > now)
+    }
+}
+
+impl Display for TimeOfDay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> String {
+        time.to_string()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", untagged)]
+pub enum Trigger {
+    Cron {
+        cron: Cron,
+    },
+    Time {
+        time: TimeOfDay,
+        #[serde(default = "every_day")]
+        days: Vec<Day>,
+    },
+    Sun {
+        sun: SunEvent,
+        /// Minutes after (or before, if negative) the event
+        #[serde(default)]
+        offset: i64,
+        #[serde(default = "every_day")]
+        days: Vec<Day>,
+    },
+}
+
+fn on_day(days: &[Day], time: &NaiveDateTime) -> bool {
+    days.iter()
+        .any(|day| *day as u32 == time.weekday().num_days_from_monday())
+}
+
+impl Trigger {
+    fn matches(&self, time: &NaiveDateTime, location: Option<&Location>) -> bool {
+        match self {
+            Trigger::Cron { cron } => cron.matches(time),
+            Trigger::Time {
+                time: time_of_day,
+                days,
+            } => {
+                time.hour() == time_of_day.hour
+                    && time.minute() == time_of_day.minute
+                    && on_day(days, time)
+            }
+            Trigger::Sun { sun, offset, days } => {
+                let location = match location {
+                    Some(location) => location,
+                    None => return false,
+                };
+
+                // the event is looked up on the day it would have to happen
+                // for the offset to land on this minute
+                let event = *time - ChronoDuration::minutes(*offset);
+
+                match sun::event_time(event.date(), location, *sun) {
+                    Some(event_time) => {
+                        let event_time = event_time.with_timezone(&Local).naive_local();
+
+                        event_time.date() == event.date()
+                            && event_time.hour() == event.hour()
+                            && event_time.minute() == event.minute()
+                            && on_day(days, time)
+                    }
+                    None => false,
+                }
+            }
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A trigger and what to change in a zone when it fires
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Schedule {
+    #[serde(flatten)]
+    trigger: Trigger,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    zone: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pattern: Option<Pattern>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    brightness: Option<u8>,
+    #[serde_as(as = "Option<DurationMilliSeconds>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transition: Option<Duration>,
+
+    #[serde(default = "default_enabled")]
+    enabled: bool,
+}
+
+impl Schedule {
+    pub fn validate(&self, zones: &Zones, location: Option<&Location>) -> Result<(), String> {
+        if let Trigger::Sun { offset, .. } = &self.trigger {
+            if location.is_none() {
+                return Err(String::from(
+                    "sun schedules require a location to be configured",
+                ));
+            }
+
+            // keep the event within a day of when the schedule runs
+            if offset.abs() >= 24 * 60 {
+                return Err(String::from("sun offset must be less than a day"));
+            }
+        }
+
+        if let Some(zone) = &self.zone {
+            if zones.get(zone).is_none() {
+                return Err(format!("unknown zone \"{}\"", zone));
+            }
+        }
+
+        match (&self.pattern, &self.preset, self.brightness) {
+            (None, None, None) => Err(String::from(
+                "schedule must set a pattern, preset or brightness",
+            )),
+            (Some(_), Some(_), _) => Err(String::from(
+                "schedule cannot set both a pattern and a preset",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    async fn run(&self, zones: &Zones, presets: &SharedPresets) -> Result<(), String> {
+        let lights = match &self.zone {
+            Some(zone) => zones
+                .get(zone)
+                .ok_or_else(|| format!("unknown zone \"{}\"", zone))?,
+            None => zones.default_zone(),
+        };
+
+        let pattern = match &self.preset {
+            Some(preset) => Some(
+                presets
+                    .get(preset)
+                    .await
+                    .ok_or_else(|| format!("unknown preset \"{}\"", preset))?,
+            ),
+            None => self.pattern.clone(),
+        };
+
+        let mut lights = lights.lock().await;
+
+        if let Some(pattern) = pattern {
+            lights.set_pattern(&pattern, self.transition);
+        }
+
+        if let Some(brightness) = self.brightness {
+            lights.set_brightness(brightness);
+        }
+
+        Ok(())
+    }
+}
+
+pub type Schedules = Collection<Schedule>;
+
+pub type SharedSchedules = Arc<Schedules>;
+
+// truncated in UTC, since local times are ambiguous while the clocks go back
+fn current_minute() -> (DateTime<Local>, Duration) {
+    let now = Utc::now();
+
+    // leap seconds are reported as nanoseconds past one billion
+    let into_minute = Duration::from_secs(now.second() as u64)
+        + Duration::from_nanos(now.nanosecond().min(999_999_999) as u64);
+
+    (
+        now.with_second(0)
+            .unwrap()
+            .with_nanosecond(0)
+            .unwrap()
+            .with_timezone(&Local),
+        Duration::from_secs(60).saturating_sub(into_minute),
+    )
+}
+
+/// Whether schedules for `minute` have already had their chance, either
+/// because it is the last minute seen or because the clocks went back and
+/// its local time came round again
+fn already_run<Tz: TimeZone>(minute: &DateTime<Tz>, last: &DateTime<Tz>) -> bool {
+    minute == last || (minute > last && minute.naive_local() <= last.naive_local())
+}
+
+/// Run enabled schedules at the start of every minute of local time
+pub async fn scheduler(
+    zones: SharedZones,
+    presets: SharedPresets,
+    schedules: SharedSchedules,
+    location: Option<Location>,
+) {
+    println!(
+        "{}{}",
+        Paint::masked("⏰ "),
+        Paint::default("Scheduler started").bold()
+    );
+
+    // schedules for the minute the daemon started in have already had their chance
+    let (mut last, mut wait) = current_minute();
+
+    loop {
+        time::sleep(wait).await;
+
+        let (minute, next) = current_minute();
+        wait = next;
+
+        if already_run(&minute, &last) {
+            continue;
+        }
+
+        last = minute;
+
+        for (name, schedule) in schedules.all().await {
+            if !schedule.enabled
+                || !schedule
+                    .trigger
+                    .matches(&minute.naive_local(), location.as_ref())
+            {
+                continue;
+            }
+
+            match schedule.run(&zones, &presets).await {
+                Ok(()) => {
+                    println!("Ran schedule {} at {}", name, minute.format("%H:%M"));
+                }
+                Err(err) => {
+                    eprintln!("Failed to run schedule {}: {}", name, err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, NaiveDate};
+
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn cron(source: &str) -> Cron {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn step_from_value_runs_to_end_of_field() {
+        let cron = cron("5/20 * * * *");
+
+        for minute in [5, 25, 45] {
+            assert!(cron.matches(&at(2024, 1, 1, 0, minute)));
+        }
+
+        for minute in [0, 6, 20, 59] {
+            assert!(!cron.matches(&at(2024, 1, 1, 0, minute)));
+        }
+    }
+
+    #[test]
+    fn lists_ranges_and_steps() {
+        let cron = cron("*/15 9-17/4,22 * * *");
+
+        assert!(cron.matches(&at(2024, 1, 1, 9, 0)));
+        assert!(cron.matches(&at(2024, 1, 1, 13, 45)));
+        assert!(cron.matches(&at(2024, 1, 1, 17, 30)));
+        assert!(cron.matches(&at(2024, 1, 1, 22, 15)));
+        assert!(!cron.matches(&at(2024, 1, 1, 10, 0)));
+        assert!(!cron.matches(&at(2024, 1, 1, 9, 10)));
+    }
+
+    #[test]
+    fn seven_is_sunday() {
+        // 2024-01-07 is a Sunday
+        for source in ["0 8 * * 7", "0 8 * * 0", "0 8 * * 6-7"] {
+            let cron = cron(source);
+
+            assert!(cron.matches(&at(2024, 1, 7, 8, 0)), "{}", source);
+            assert!(!cron.matches(&at(2024, 1, 8, 8, 0)), "{}", source);
+        }
+    }
+
+    #[test]
+    fn restricted_day_of_month_and_week_match_either() {
+        let cron = cron("0 12 1 * 1");
+
+        // the 1st, which is a Monday
+        assert!(cron.matches(&at(2024, 1, 1, 12, 0)));
+        // a Monday that is not the 1st
+        assert!(cron.matches(&at(2024, 1, 8, 12, 0)));
+        // the 1st, which is a Thursday
+        assert!(cron.matches(&at(2024, 2, 1, 12, 0)));
+        // neither
+        assert!(!cron.matches(&at(2024, 1, 9, 12, 0)));
+    }
+
+    #[test]
+    fn unrestricted_day_field_does_not_widen_the_other() {
+        assert!(!cron("0 12 * * 1").matches(&at(2024, 2, 1, 12, 0)));
+        assert!(!cron("0 12 1 * *").matches(&at(2024, 1, 8, 12, 0)));
+
+        // a stepped `*` is still unrestricted, so only odd days that are Mondays match
+        let cron_step = cron("0 0 */2 * 1");
+        assert!(cron_step.matches(&at(2024, 1, 1, 0, 0)));
+        assert!(!cron_step.matches(&at(2024, 1, 8, 0, 0)));
+        assert!(!cron_step.matches(&at(2024, 1, 3, 0, 0)));
+
+        assert!(cron("0 12 * 2 *").matches(&at(2024, 2, 14, 12, 0)));
+        assert!(!cron("0 12 * 2 *").matches(&at(2024, 3, 14, 12, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for source in [
+            "*/0 * * * *",
+            "5/0 * * * *",
+            "0 17-9 * * *",
+            "60 * * * *",
+            "0 24 * * *",
+            "0 0 0 * *",
+            "0 0 * 13 *",
+            "0 0 * * 8",
+            "0 0 * *",
+            "0 0 * * * *",
+            "a * * * *",
+            "1-2-3 * * * *",
+        ] {
+            assert!(source.parse::<Cron>().is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn repeated_minutes_run_once_when_clocks_go_back() {
+        let summer = FixedOffset::east_opt(2 * 3600).unwrap();
+        let winter = FixedOffset::east_opt(3600).unwrap();
+        let local = |offset: FixedOffset, hour, minute| {
+            offset
+                .from_local_datetime(&at(2024, 10, 27, hour, minute))
+                .unwrap()
+        };
+
+        let last = local(summer, 2, 59);
+
+        assert!(already_run(&last, &last));
+        assert!(already_run(&local(winter, 2, 0), &last));
+        assert!(already_run(&local(winter, 2, 59), &last));
+        assert!(!already_run(&local(winter, 3, 0), &last));
+        assert!(!already_run(&local(summer, 3, 0), &last));
+    }
+
+    #[test]
+    fn parses_time_of_day() {
+        let time = TimeOfDay::try_from(String::from("07:05")).unwrap();
+
+        assert_eq!(time.to_string(), "07:05");
+
+        for source in ["24:00", "12:60", "1200", "12:xx"] {
+            assert!(
+                TimeOfDay::try_from(String::from(source)).is_err(),
+                "{}",
+                source
+            );
+        }
+    }
+}
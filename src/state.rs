@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use rocket::serde::{Deserialize, Serialize};
+use rocket::tokio::task;
+use rocket::tokio::time;
+use rocket::tokio::time::Duration;
+
+use crate::storage;
+use crate::{Pattern, SharedZones};
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct ZoneState {
+    pub pattern: Pattern,
+    pub brightness: u8,
+}
+
+pub type SavedState = BTreeMap<String, ZoneState>;
+
+async fn revisions(zones: &SharedZones) -> BTreeMap<String, u64> {
+    let mut revisions = BTreeMap::new();
+
+    for (name, lights) in zones.lights.iter() {
+        revisions.insert(name.clone(), lights.lock().await.revision());
+    }
+
+    revisions
+}
+
+/// Periodically save the pattern and brightness of every zone whenever any of
+/// them has been changed since the last save
+pub async fn state_saver(zones: SharedZones, path: PathBuf, period: Duration) {
+    let mut saved = revisions(&zones).await;
+
+    let mut interval = time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        if revisions(&zones).await == saved {
+            continue;
+        }
+
+        let mut state = SavedState::new();
+        let mut current = BTreeMap::new();
+
+        for (name, lights) in zones.lights.iter() {
+            let lights = lights.lock().await;
+
+            current.insert(name.clone(), lights.revision());
+            state.insert(
+                name.clone(),
+                ZoneState {
+                    pattern: lights.get_pattern().clone(),
+                    brightness: lights.get_brightness(),
+                },
+            );
+        }
+
+        // the write and fsync can stall on slow storage, so keep them off
+        // the async worker threads
+        let state_path = path.clone();
+        let result = task::spawn_blocking(move || storage::save(&state_path, &state)).await;
+
+        match result {
+            Ok(Ok(())) => {
+                saved = current;
+            }
+            Ok(Err(err)) => {
+                eprintln!("Failed to save light state: {}", err);
+            }
+            Err(err) => {
+                eprintln!("Failed to save light state: {}", err);
+            }
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use rocket::serde::de::DeserializeOwned;
+use rocket::serde::json::serde_json;
+use rocket::serde::Serialize;
+use rocket::tokio::sync::Mutex;
+use rocket::tokio::task;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(PathBuf, IoError),
+    Json(PathBuf, serde_json::Error),
+}
+
+impl Error for StorageError {}
+
+impl Display for StorageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            StorageError::Io(path, err) => {
+                write!(f, "failed to access {}: {}", path.display(), err)
+            }
+            StorageError::Json(path, err) => {
+                write!(f, "invalid JSON in {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+/// Read a JSON file, returning `None` if it does not exist yet
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, StorageError> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(StorageError::Io(path.to_path_buf(), err)),
+    };
+
+    serde_json::from_slice(&contents)
+        .map(Some)
+        .map_err(|err| StorageError::Json(path.to_path_buf(), err))
+}
+
+fn to_json<T: Serialize>(path: &Path, value: &T) -> Result<Vec<u8>, StorageError> {
+    serde_json::to_vec_pretty(value).map_err(|err| StorageError::Json(path.to_path_buf(), err))
+}
+
+/// Write a file by writing a temporary file next to it and renaming it into
+/// place, so a crash or power loss never leaves a truncated file behind
+fn write(path: &Path, contents: &[u8]) -> Result<(), StorageError> {
+    let mut temporary = OsString::from(path.as_os_str());
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let write = || {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+
+        fs::rename(&temporary, path)
+    };
+
+    write().map_err(|err| StorageError::Io(path.to_path_buf(), err))
+}
+
+/// Write a JSON file, blocking until it is on disk
+pub fn save<T: Serialize>(path: &Path, value: &T) -> Result<(), StorageError> {
+    write(path, &to_json(path, value)?)
+}
+
+/// Named values, saved to a JSON file whenever they change
+pub struct Collection<T> {
+    path: PathBuf,
+    items: Mutex<BTreeMap<String, T>>,
+}
+
+impl<T: Clone + Serialize + DeserializeOwned> Collection<T> {
+    pub fn load(path: PathBuf) -> Result<Collection<T>, StorageError> {
+        let items = load(&path)?.unwrap_or_default();
+
+        Ok(Collection {
+            path,
+            items: Mutex::new(items),
+        })
+    }
+
+    pub async fn all(&self) -> BTreeMap<String, T> {
+        self.items.lock().await.clone()
+    }
+
+    pub async fn get(&self, name: &str) -> Option<T> {
+        self.items.lock().await.get(name).cloned()
+    }
+
+    /// Look up a value without locking, before the collection is shared
+    /// with the server
+    pub fn get_unshared(&mut self, name: &str) -> Option<&T> {
+        self.items.get_mut().get(name)
+    }
+
+    // serializes under the caller's lock, which stays held so writes land in
+    // order, but leaves the write and fsync to the blocking thread pool
+    async fn persist(&self, items: &BTreeMap<String, T>) -> Result<(), StorageError> {
+        let contents = to_json(&self.path, items)?;
+        let path = self.path.clone();
+
+        task::spawn_blocking(move || write(&path, &contents))
+            .await
+            .map_err(|err| StorageError::Io(self.path.clone(), IoError::other(err)))?
+    }
+
+    pub async fn insert(&self, name: String, item: T) -> Result<(), StorageError> {
+        let mut items = self.items.lock().await;
+
+        // only keep the change if it made it to disk
+        let mut updated = items.clone();
+        updated.insert(name, item);
+        self.persist(&updated).await?;
+
+        *items = updated;
+
+        Ok(())
+    }
+
+    pub async fn remove(&self, name: &str) -> Result<Option<T>, StorageError> {
+        let mut items = self.items.lock().await;
+
+        if !items.contains_key(name) {
+            return Ok(None);
+        }
+
+        let mut updated = items.clone();
+        let removed = updated.remove(name);
+        self.persist(&updated).await?;
+
+        *items = updated;
+
+        Ok(removed)
+    }
+}
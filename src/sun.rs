@@ -0,0 +1,193 @@
+use std::f64::consts::PI;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use rocket::serde::{Deserialize, Serialize};
+
+use crate::config::Location;
+
+// Julian day of the Unix epoch and of the J2000 epoch
+const JULIAN_UNIX_EPOCH: f64 = 2440587.5;
+const JULIAN_2000: f64 = 2451545.0;
+
+// obliquity of the ecliptic
+const AXIAL_TILT: f64 = 23.4397;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde", rename_all = "kebab-case")]
+pub enum SunEvent {
+    CivilDawn,
+    Sunrise,
+    Sunset,
+    CivilDusk,
+}
+
+impl SunEvent {
+    // altitude of the sun's center at the event, accounting for refraction
+    // and the size of the sun's disc at sunrise and sunset
+    fn altitude(self) -> f64 {
+        match self {
+            SunEvent::Sunrise | SunEvent::Sunset => -0.833,
+            SunEvent::CivilDawn | SunEvent::CivilDusk => -6.0,
+        }
+    }
+
+    fn rising(self) -> bool {
+        matches!(self, SunEvent::CivilDawn | SunEvent::Sunrise)
+    }
+}
+
+fn sin(degrees: f64) -> f64 {
+    (degrees * PI / 180.0).sin()
+}
+
+fn cos(degrees: f64) -> f64 {
+    (degrees * PI / 180.0).cos()
+}
+
+/// Time of a sun event on the given date using the sunrise equation, or
+/// `None` if the sun does not cross that altitude on that day (e.g. during
+/// polar day or night)
+pub fn event_time(date: NaiveDate, location: &Location, event: SunEvent) -> Option<DateTime<Utc>> {
+    let unix_days = date
+        .signed_duration_since(NaiveDate::from_ymd_opt(1970, 1, 1)?)
+        .num_days();
+
+    // mean solar noon, counted in days since J2000
+    let day = (unix_days as f64 + JULIAN_UNIX_EPOCH - JULIAN_2000 + 0.0008).ceil();
+    let noon = day - location.longitude / 360.0;
+
+    let anomaly = (357.5291 + 0.98560028 * noon).rem_euclid(360.0);
+    let center = 1.9148 * sin(anomaly) + 0.0200 * sin(2.0 * anomaly) + 0.0003 * sin(3.0 * anomaly);
+    let ecliptic_longitude = (anomaly + center + 180.0 + 102.9372).rem_euclid(360.0);
+
+    let transit =
+        JULIAN_2000 + noon + 0.0053 * sin(anomaly) - 0.0069 * sin(2.0 * ecliptic_longitude);
+
+    let declination = (sin(ecliptic_longitude) * sin(AXIAL_TILT)).asin() * 180.0 / PI;
+
+    let hour_angle = (sin(event.altitude()) - sin(location.latitude) * sin(declination))
+        / (cos(location.latitude) * cos(declination));
+
+    if !(-1.0..=1.0).contains(&hour_angle) {
+        return None;
+    }
+
+    let hour_angle = hour_angle.acos() * 180.0 / PI;
+
+    let julian = if event.rising() {
+        transit - hour_angle / 360.0
+    } else {
+        transit + hour_angle / 360.0
+    };
+
+    DateTime::from_timestamp(((julian - JULIAN_UNIX_EPOCH) * 86400.0).round() as i64, 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDateTime, TimeZone};
+
+    use super::*;
+
+    const LONDON: Location = Location {
+        latitude: 51.5074,
+        longitude: -0.1278,
+    };
+    const SAN_FRANCISCO: Location = Location {
+        latitude: 37.7749,
+        longitude: -122.4194,
+    };
+    const SYDNEY: Location = Location {
+        latitude: -33.8688,
+        longitude: 151.2093,
+    };
+    const TROMSO: Location = Location {
+        latitude: 69.6492,
+        longitude: 18.9553,
+    };
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    // published times to the minute, allowing for differences in the
+    // refraction and orbital models
+    fn assert_near(actual: Option<DateTime<Utc>>, expected: &str) {
+        let expected = Utc
+            .from_utc_datetime(&NaiveDateTime::parse_from_str(expected, "%Y-%m-%d %H:%M").unwrap());
+        let actual = actual.expect("the sun should rise and set");
+
+        assert!(
+            (actual - expected).num_seconds().abs() <= 120,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn london_midsummer() {
+        let day = date(2024, 6, 21);
+
+        // 04:43 and 21:21 BST
+        assert_near(
+            event_time(day, &LONDON, SunEvent::Sunrise),
+            "2024-06-21 03:43",
+        );
+        assert_near(
+            event_time(day, &LONDON, SunEvent::Sunset),
+            "2024-06-21 20:21",
+        );
+    }
+
+    #[test]
+    fn san_francisco_midwinter() {
+        let day = date(2024, 12, 21);
+
+        // 07:21 and 16:54 PST, where sunset is already the next day in UTC
+        assert_near(
+            event_time(day, &SAN_FRANCISCO, SunEvent::Sunrise),
+            "2024-12-21 15:21",
+        );
+        assert_near(
+            event_time(day, &SAN_FRANCISCO, SunEvent::Sunset),
+            "2024-12-22 00:54",
+        );
+    }
+
+    #[test]
+    fn sydney_southern_summer() {
+        let day = date(2024, 12, 21);
+
+        // 05:41 and 20:05 AEDT, where sunrise is still the previous day in UTC
+        assert_near(
+            event_time(day, &SYDNEY, SunEvent::Sunrise),
+            "2024-12-20 18:41",
+        );
+        assert_near(
+            event_time(day, &SYDNEY, SunEvent::Sunset),
+            "2024-12-21 09:05",
+        );
+    }
+
+    #[test]
+    fn civil_twilight_surrounds_sunrise_and_sunset() {
+        let day = date(2024, 3, 20);
+
+        let dawn = event_time(day, &LONDON, SunEvent::CivilDawn).unwrap();
+        let sunrise = event_time(day, &LONDON, SunEvent::Sunrise).unwrap();
+        let sunset = event_time(day, &LONDON, SunEvent::Sunset).unwrap();
+        let dusk = event_time(day, &LONDON, SunEvent::CivilDusk).unwrap();
+
+        assert!(dawn < sunrise && sunrise < sunset && sunset < dusk);
+        assert!((sunrise - dawn).num_minutes() > 20 && (sunrise - dawn).num_minutes() < 45);
+        assert!((dusk - sunset).num_minutes() > 20 && (dusk - sunset).num_minutes() < 45);
+    }
+
+    #[test]
+    fn polar_day_and_night() {
+        assert!(event_time(date(2024, 12, 21), &TROMSO, SunEvent::Sunrise).is_none());
+        assert!(event_time(date(2024, 6, 21), &TROMSO, SunEvent::Sunset).is_none());
+    }
+}